@@ -22,7 +22,7 @@ pub fn part_a() {
 
     let result: i32 = left
         .into_iter()
-        .zip(right.into_iter())
+        .zip(right)
         .map(|(l, r)| (l - r).abs())
         .sum();
 
@@ -1,5 +1,56 @@
 use anyhow::Result;
-use std::fs;
+use std::{fmt, fs};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reason {
+    TrendChanged,
+    StepTooLarge,
+    ZeroStep,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+    Safe,
+    SafeAfterRemoving {
+        index: usize,
+        value: i32,
+    },
+    Unsafe {
+        first_violation_index: usize,
+        reason: Reason,
+    },
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Reason::TrendChanged => write!(f, "trend changed"),
+            Reason::StepTooLarge => write!(f, "step too large"),
+            Reason::ZeroStep => write!(f, "zero step"),
+        }
+    }
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Verdict::Safe => write!(f, "safe"),
+            Verdict::SafeAfterRemoving { index, value } => {
+                write!(f, "safe after removing {value} at index {index}")
+            }
+            Verdict::Unsafe {
+                first_violation_index,
+                reason,
+            } => write!(f, "unsafe at index {first_violation_index}: {reason}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValueRepair {
+    pub changes: usize,
+    pub levels: Vec<i32>,
+}
 
 pub struct Reports(Vec<i32>);
 
@@ -15,57 +66,61 @@ impl Reports {
 
         let is_increasing = diffs.iter().all(|v| *v > 0);
         let is_decreasing = diffs.iter().all(|v| *v < 0);
-        let within_bounds = diffs.iter().map(|v| v.abs()).all(|v| v >= 1 && v <= 3);
+        let within_bounds = diffs.iter().map(|v| v.abs()).all(|v| (1..=3).contains(&v));
 
         (is_increasing || is_decreasing) && within_bounds
     }
 
-    /// Tring to be smart and find the faulty element and removing it taking into account
-    /// that the index of the faulty element is either the on that we find in the diff array
-    /// or the next one.
+    /// Problem Dampener check in a single pass. For each trend we know whether every prefix
+    /// and every suffix of the report is safe, so removing `index` works when the prefix
+    /// before it, the suffix after it and the step bridging its two neighbours are all safe.
     pub fn is_valid_part_b(&self) -> bool {
-        let mut diffs: Vec<i32> = Vec::with_capacity(self.0.len() - 1);
+        dampened_index(&self.0, true).is_some() || dampened_index(&self.0, false).is_some()
+    }
 
-        for index in 1..self.0.len() {
-            let prev = self.0[index - 1];
-            let current = self.0[index];
-            diffs.push(current - prev);
+    /// Explains why a report is (un)safe. The violation index points at the level that
+    /// ends the first offending step, with the trend taken from the first step.
+    pub fn verdict(&self) -> Verdict {
+        let Some((first_violation_index, reason)) = first_violation(&self.0) else {
+            return Verdict::Safe;
+        };
+
+        let removal = dampened_index(&self.0, true).or_else(|| dampened_index(&self.0, false));
+
+        match removal {
+            Some(index) => Verdict::SafeAfterRemoving {
+                index,
+                value: self.0[index],
+            },
+            None => Verdict::Unsafe {
+                first_violation_index,
+                reason,
+            },
         }
+    }
 
-        let is_increasing = diffs.iter().all(|v| *v > 0);
-        let is_decreasing = diffs.iter().all(|v| *v < 0);
-        let within_bounds = diffs.iter().map(|v| v.abs()).all(|v| v >= 1 && v <= 3);
-
-        let is_valid = (is_increasing || is_decreasing) && within_bounds;
-        if is_valid {
-            true
-        } else {
-            let count_positive = diffs.iter().filter(|v| **v > 0).count();
-            let count_negative = diffs.iter().filter(|v| **v < 0).count();
-            let count_zero = diffs.iter().filter(|v| **v == 0).count();
-            let count_out_of_bounds = diffs.iter().map(|v| v.abs()).filter(|v| *v > 3).count();
-
-            let pad: Option<usize> = if count_negative == 1 {
-                Some(diffs.iter().position(|v| *v < 0).unwrap())
-            } else if count_positive == 1 {
-                Some(diffs.iter().position(|v| *v > 0).unwrap())
-            } else if count_zero == 1 {
-                Some(diffs.iter().position(|v| *v == 0).unwrap())
-            } else if count_out_of_bounds == 1 {
-                Some(diffs.iter().position(|v| *v < 1 || *v > 3).unwrap())
-            } else {
-                None
+    /// Minimum number of levels whose values must change for the report to be safe, along
+    /// with one repaired report. Two levels `i < j` can both be kept when their difference
+    /// fits `j - i` steps of 1 to 3, so we look for the longest chain of such levels and
+    /// fill in the gaps between them.
+    pub fn value_repair(&self) -> ValueRepair {
+        let levels = &self.0;
+        if levels.is_empty() {
+            return ValueRepair {
+                changes: 0,
+                levels: vec![],
             };
+        }
 
-            if let Some(index) = pad {
-                let mut raw1 = self.0.clone();
-                let mut raw2 = self.0.clone();
-                raw1.remove(index);
-                raw2.remove(index + 1);
-                Reports(raw1).is_valid_part_a() || Reports(raw2).is_valid_part_a()
-            } else {
-                false
-            }
+        let (kept, trend) = [1, -1]
+            .into_iter()
+            .map(|trend| (longest_keepable_chain(levels, trend), trend))
+            .max_by_key(|(chain, _)| chain.len())
+            .unwrap();
+
+        ValueRepair {
+            changes: levels.len() - kept.len(),
+            levels: fill_gaps(levels, &kept, trend),
         }
     }
 
@@ -86,6 +141,122 @@ impl Reports {
     }
 }
 
+fn is_safe_step(prev: i32, next: i32, increasing: bool) -> bool {
+    let diff = if increasing { next - prev } else { prev - next };
+    (1..=3).contains(&diff)
+}
+
+fn first_violation(levels: &[i32]) -> Option<(usize, Reason)> {
+    let trend = levels.windows(2).next().map(|w| (w[1] - w[0]).signum())?;
+
+    levels.windows(2).enumerate().find_map(|(index, w)| {
+        let diff = w[1] - w[0];
+        let reason = if diff == 0 {
+            Reason::ZeroStep
+        } else if diff.signum() != trend {
+            Reason::TrendChanged
+        } else if diff.abs() > 3 {
+            Reason::StepTooLarge
+        } else {
+            return None;
+        };
+
+        Some((index + 1, reason))
+    })
+}
+
+/// Index of a level whose removal leaves the report safe for the given trend.
+fn dampened_index(levels: &[i32], increasing: bool) -> Option<usize> {
+    let len = levels.len();
+    if len == 0 {
+        return None;
+    }
+
+    // prefix[i]: levels[..=i] is safe, suffix[i]: levels[i..] is safe
+    let mut prefix = vec![true; len];
+    let mut suffix = vec![true; len];
+
+    for index in 1..len {
+        prefix[index] =
+            prefix[index - 1] && is_safe_step(levels[index - 1], levels[index], increasing);
+    }
+
+    for index in (0..len - 1).rev() {
+        suffix[index] =
+            suffix[index + 1] && is_safe_step(levels[index], levels[index + 1], increasing);
+    }
+
+    (0..len).find(|&skip| {
+        let left = skip == 0 || prefix[skip - 1];
+        let right = skip == len - 1 || suffix[skip + 1];
+        let bridge = skip == 0
+            || skip == len - 1
+            || is_safe_step(levels[skip - 1], levels[skip + 1], increasing);
+
+        left && right && bridge
+    })
+}
+
+fn can_keep_both(levels: &[i32], from: usize, to: usize, trend: i32) -> bool {
+    let steps = (to - from) as i32;
+    let diff = (levels[to] - levels[from]) * trend;
+    steps <= diff && diff <= 3 * steps
+}
+
+/// Indices of the longest set of levels that can stay untouched for the given trend.
+fn longest_keepable_chain(levels: &[i32], trend: i32) -> Vec<usize> {
+    let len = levels.len();
+    let mut best = vec![1; len];
+    let mut previous: Vec<Option<usize>> = vec![None; len];
+
+    for to in 0..len {
+        for from in 0..to {
+            if best[from] + 1 > best[to] && can_keep_both(levels, from, to, trend) {
+                best[to] = best[from] + 1;
+                previous[to] = Some(from);
+            }
+        }
+    }
+
+    let mut current = (0..len).max_by_key(|&index| best[index]);
+    let mut chain = Vec::new();
+    while let Some(index) = current {
+        chain.push(index);
+        current = previous[index];
+    }
+
+    chain.reverse();
+    chain
+}
+
+/// Rebuilds a safe report keeping the levels at `kept` and spreading the other steps evenly.
+fn fill_gaps(levels: &[i32], kept: &[usize], trend: i32) -> Vec<i32> {
+    let mut repaired = levels.to_vec();
+    let first = kept[0];
+    let last = kept[kept.len() - 1];
+
+    for (index, value) in repaired.iter_mut().enumerate() {
+        if index < first {
+            *value = levels[first] - trend * (first - index) as i32;
+        } else if index > last {
+            *value = levels[last] + trend * (index - last) as i32;
+        }
+    }
+
+    for pair in kept.windows(2) {
+        let (from, to) = (pair[0], pair[1]);
+        let steps = (to - from) as i32;
+        let diff = (levels[to] - levels[from]) * trend;
+
+        for offset in 1..steps {
+            let covered = diff * offset / steps;
+            repaired[from + offset as usize] = levels[from] + trend * covered;
+        }
+    }
+
+    repaired
+}
+
 pub fn load_data() -> Result<Vec<Reports>> {
     let content = fs::read_to_string("./src/input/02.input")?;
     let mut reports: Vec<Reports> = Vec::new();
@@ -98,6 +269,14 @@ pub fn load_data() -> Result<Vec<Reports>> {
     Ok(reports)
 }
 
+pub fn analyze() {
+    let reports = load_data().unwrap();
+
+    for (line, report) in reports.iter().enumerate() {
+        println!("{:>4}: {:?} -> {}", line + 1, report.0, report.verdict());
+    }
+}
+
 pub fn part_a() {
     let reports = load_data().unwrap();
     let safe_reports: Vec<&Reports> = reports.iter().filter(|r| r.is_valid_part_a()).collect();
@@ -106,66 +285,230 @@ pub fn part_a() {
 
 pub fn part_b() {
     let reports = load_data().unwrap();
-    let safe_reports: Vec<&Reports> = reports
-        .iter()
-        .filter(|r| r.is_valid_part_b_brute())
-        .collect();
+    let safe_reports: Vec<&Reports> = reports.iter().filter(|r| r.is_valid_part_b()).collect();
     println!("Part b: {:?}", safe_reports.len());
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::test_util::Lcg;
+
+    fn random_report(rng: &mut Lcg) -> Reports {
+        let len = rng.range(1, 8) as usize;
+        let mut levels = vec![rng.range(1, 20)];
+
+        for _ in 1..len {
+            let last = *levels.last().unwrap();
+            levels.push(last + rng.range(-5, 5));
+        }
+
+        Reports(levels)
+    }
+
+    #[test]
+    fn test_part_b_matches_brute_force() {
+        let mut rng = Lcg(0x2024_1202);
+
+        for _ in 0..2_000_000 {
+            let report = random_report(&mut rng);
+            assert_eq!(
+                report.is_valid_part_b(),
+                report.is_valid_part_b_brute(),
+                "{:?}",
+                report.0
+            );
+        }
+    }
+
+    #[test]
+    fn test_verdicts() {
+        let verdicts: Vec<Verdict> = [
+            vec![7, 6, 4, 2, 1],
+            vec![1, 2, 7, 8, 9],
+            vec![9, 7, 6, 2, 1],
+            vec![1, 3, 2, 4, 5],
+            vec![8, 6, 4, 4, 1],
+            vec![1, 3, 6, 7, 9],
+        ]
+        .into_iter()
+        .map(|levels| Reports(levels).verdict())
+        .collect();
+
+        assert_eq!(
+            verdicts,
+            vec![
+                Verdict::Safe,
+                Verdict::Unsafe {
+                    first_violation_index: 2,
+                    reason: Reason::StepTooLarge
+                },
+                Verdict::Unsafe {
+                    first_violation_index: 3,
+                    reason: Reason::StepTooLarge
+                },
+                Verdict::SafeAfterRemoving { index: 1, value: 3 },
+                Verdict::SafeAfterRemoving { index: 2, value: 4 },
+                Verdict::Safe,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_verdict_reasons() {
+        assert_eq!(
+            Reports(vec![1, 1, 1, 1]).verdict(),
+            Verdict::Unsafe {
+                first_violation_index: 1,
+                reason: Reason::ZeroStep
+            }
+        );
+        assert_eq!(
+            Reports(vec![1, 2, 1, 2, 1]).verdict(),
+            Verdict::Unsafe {
+                first_violation_index: 2,
+                reason: Reason::TrendChanged
+            }
+        );
+    }
+
+    #[test]
+    fn test_verdict_agrees_with_part_b() {
+        let mut rng = Lcg(0x2024_0227);
+
+        for _ in 0..100_000 {
+            let report = random_report(&mut rng);
+            let safe = !matches!(report.verdict(), Verdict::Unsafe { .. });
+            assert_eq!(safe, report.is_valid_part_b_brute(), "{:?}", report.0);
+        }
+    }
+
+    /// Exhaustive search over which levels change and, for each trend, every value the changed
+    /// levels can take.
+    fn value_repair_brute(levels: &[i32]) -> usize {
+        fn fill(levels: &[i32], mask: u32, index: usize, prev: Option<i32>, trend: i32) -> bool {
+            if index == levels.len() {
+                return true;
+            }
+
+            let candidates: Vec<i32> = if mask & (1 << index) == 0 {
+                vec![levels[index]]
+            } else if let Some(prev) = prev {
+                (1..=3).map(|step| prev + trend * step).collect()
+            } else {
+                let span = 3 * levels.len() as i32;
+                let low = *levels.iter().min().unwrap() - span;
+                let high = *levels.iter().max().unwrap() + span;
+                (low..=high).collect()
+            };
+
+            candidates.into_iter().any(|value| {
+                let step_ok = match prev {
+                    Some(prev) => (1..=3).contains(&((value - prev) * trend)),
+                    None => true,
+                };
+                step_ok && fill(levels, mask, index + 1, Some(value), trend)
+            })
+        }
+
+        (0..1u32 << levels.len())
+            .filter(|&mask| {
+                [1, -1]
+                    .iter()
+                    .any(|&trend| fill(levels, mask, 0, None, trend))
+            })
+            .map(|mask| mask.count_ones() as usize)
+            .min()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_value_repair() {
+        // 2 -> 8 fits in two steps, so only the 7 has to change
+        let repair = Reports(vec![1, 2, 7, 8, 9]).value_repair();
+        assert_eq!(repair.changes, 1);
+        assert!(Reports(repair.levels).is_valid_part_a());
+
+        let repair = Reports(vec![7, 6, 4, 2, 1]).value_repair();
+        assert_eq!(
+            repair,
+            ValueRepair {
+                changes: 0,
+                levels: vec![7, 6, 4, 2, 1]
+            }
+        );
+    }
+
+    #[test]
+    fn test_value_repair_matches_brute_force() {
+        let mut rng = Lcg(0x2024_0228);
+
+        for _ in 0..3_000 {
+            let len = rng.range(1, 6) as usize;
+            let levels: Vec<i32> = (0..len).map(|_| rng.range(1, 12)).collect();
+            let repair = Reports(levels.clone()).value_repair();
+
+            let changed = levels
+                .iter()
+                .zip(&repair.levels)
+                .filter(|(a, b)| a != b)
+                .count();
+
+            assert_eq!(repair.changes, value_repair_brute(&levels), "{:?}", levels);
+            assert_eq!(changed, repair.changes, "{:?}", levels);
+            assert!(Reports(repair.levels).is_valid_part_a(), "{:?}", levels);
+        }
+    }
 
     #[test]
     fn test_is_valid_part_b() {
         let reports = Reports(vec![7, 6, 4, 2, 1]);
-        assert_eq!(reports.is_valid_part_b(), true);
+        assert!(reports.is_valid_part_b());
     }
 
     #[test]
     fn test_part_b_type_1_repair() {
         // removing 3 will make this report valid
         let reports = Reports(vec![1, 3, 2, 4, 5]);
-        assert_eq!(reports.is_valid_part_b(), true);
+        assert!(reports.is_valid_part_b());
     }
 
     #[test]
     fn test_part_b_type_2_repair() {
         // removing 4 will make this report valid
         let reports = Reports(vec![8, 6, 4, 4, 1]);
-        assert_eq!(reports.is_valid_part_b(), true);
+        assert!(reports.is_valid_part_b());
     }
 
     #[test]
     fn test_part_b_start_repair() {
         // Removing first element will make this valid
         let reports = Reports(vec![1, 6, 4, 2, 1]);
-        assert_eq!(reports.is_valid_part_b(), true);
+        assert!(reports.is_valid_part_b());
     }
 
     #[test]
     fn test_part_b_end_repair() {
         // Removing last element will make this valid
         let reports = Reports(vec![8, 6, 4, 2, 4]);
-        assert_eq!(reports.is_valid_part_b(), true);
+        assert!(reports.is_valid_part_b());
     }
 
     #[test]
     fn test_part_b_mid_repair() {
         // Removing last 6 will make this valid
         let reports = Reports(vec![8, 6, 4, 6, 1]);
-        assert_eq!(reports.is_valid_part_b(), true);
+        assert!(reports.is_valid_part_b());
     }
 
     #[test]
     fn test_part_b_usafe_reports() {
         // Removing last element will make this valid
         let reports = Reports(vec![1, 2, 7, 8, 9]);
-        assert_eq!(reports.is_valid_part_b(), false);
+        assert!(!reports.is_valid_part_b());
 
         let reports = Reports(vec![9, 7, 6, 2, 1]);
-        assert_eq!(reports.is_valid_part_b(), false);
+        assert!(!reports.is_valid_part_b());
     }
 
     #[test]
@@ -173,28 +516,28 @@ mod test {
         // Removing last element will make this valid
         // diffs: [1, 1, 1, 1, 2, 1, 4]
         let reports = Reports(vec![19, 20, 21, 22, 23, 25, 26, 30]);
-        assert_eq!(reports.is_valid_part_b(), true);
+        assert!(reports.is_valid_part_b());
     }
 
     #[test]
     fn test_part_b_type_3_repair_start() {
         // Removing first element will make this valid
         let reports = Reports(vec![30, 26, 25, 23, 22, 21, 20, 19]);
-        assert_eq!(reports.is_valid_part_b(), true);
+        assert!(reports.is_valid_part_b());
     }
 
     #[test]
     fn test_part_b_type_3_repair_mid() {
         // Removing first element will make this valid
         let reports = Reports(vec![26, 25, 23, 30, 22, 21, 20, 19]);
-        assert_eq!(reports.is_valid_part_b(), true);
+        assert!(reports.is_valid_part_b());
     }
 
     #[test]
     fn test_part_b_type_3_repair_other() {
         // Removing first element will make this valid
         let reports = Reports(vec![26, 25, 23, 22, 21, 20, 30, 19]);
-        assert_eq!(reports.is_valid_part_b(), true);
+        assert!(reports.is_valid_part_b());
     }
 
     #[test]
@@ -216,6 +559,6 @@ mod test {
             Reports(vec![1, 2, 3, 4, 5, 5]).is_valid_part_b(),
         ];
 
-        assert!(reports.iter().all(|b| *b == true));
+        assert!(reports.iter().all(|b| *b));
     }
 }
@@ -1,81 +1,671 @@
 use anyhow::Result;
-use regex::Regex;
-use std::fs;
+use std::{
+    fs::{self, File},
+    io::{IsTerminal, Read},
+};
 
-#[derive(Debug, PartialEq)]
-enum Instruction {
-    Mul(i32, i32),
-    Do,
-    Donot,
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction {
+    pub name: &'static str,
+    pub args: Vec<i32>,
 }
 
-pub fn load_data() -> Result<String> {
-    let content = fs::read_to_string("./src/input/03.input")?;
-    Ok(content)
+impl Instruction {
+    pub fn new(name: &'static str, args: &[i32]) -> Self {
+        Self {
+            name,
+            args: args.to_vec(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MachineState {
+    pub enabled: bool,
+    pub accumulator: i64,
+}
+
+impl Default for MachineState {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            accumulator: 0,
+        }
+    }
 }
 
-fn parse_a(input: &str) -> i32 {
-    let re = Regex::new(r"mul\((?P<left>\d+),(?P<right>\d+)\)").unwrap();
-    re.captures_iter(input)
-        .map(|v| v.extract())
-        .map(|(_, [left, right])| {
-            let a = left.parse::<i32>().unwrap();
-            let b = right.parse::<i32>().unwrap();
-            a * b
+pub type Handler = fn(&mut MachineState, &[i32]);
+
+/// An instruction the lexer recognises as `name(a,b,...)` with exactly `arity` operands.
+/// Conditional instructions are skipped while the machine is disabled.
+#[derive(Debug, Clone)]
+pub struct InstructionSpec {
+    pub name: &'static str,
+    pub arity: usize,
+    pub conditional: bool,
+    pub handler: Handler,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct InstructionSet {
+    specs: Vec<InstructionSpec>,
+}
+
+impl InstructionSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only `mul`, as used by part a.
+    pub fn multiply_only() -> Self {
+        Self::new().register(InstructionSpec {
+            name: "mul",
+            arity: 2,
+            conditional: true,
+            handler: |state, args| state.accumulator += args[0] as i64 * args[1] as i64,
         })
-        .sum()
-}
-
-fn parse_b(input: &str) -> Vec<Instruction> {
-    let mul_re = Regex::new(r"mul\((?P<left>\d+),(?P<right>\d+)\)").unwrap();
-    let re = Regex::new(r"mul\((?P<left>\d+),(?P<right>\d+)\)|do\(\)|don\'t\(\)").unwrap();
-
-    re.find_iter(input)
-        .map(|value| {
-            let left = value.as_str();
-            match left {
-                "do()" => Instruction::Do,
-                "don't()" => Instruction::Donot,
-                _ => {
-                    let (_, [left, right]) = mul_re.captures(left).unwrap().extract();
-                    let left = left.parse::<i32>().unwrap();
-                    let right = right.parse::<i32>().unwrap();
-                    Instruction::Mul(left, right)
+    }
+
+    /// `mul` plus the `do()` / `don't()` toggles, as used by part b.
+    pub fn standard() -> Self {
+        Self::multiply_only()
+            .register(InstructionSpec {
+                name: "do",
+                arity: 0,
+                conditional: false,
+                handler: |state, _| state.enabled = true,
+            })
+            .register(InstructionSpec {
+                name: "don't",
+                arity: 0,
+                conditional: false,
+                handler: |state, _| state.enabled = false,
+            })
+    }
+
+    pub fn register(mut self, spec: InstructionSpec) -> Self {
+        self.specs.push(spec);
+        self
+    }
+
+    pub fn spec(&self, name: &str) -> Option<&InstructionSpec> {
+        self.specs.iter().find(|spec| spec.name == name)
+    }
+
+    /// Longest text any registered instruction can span, i.e. `name(` plus three digits per
+    /// operand, the commas between them and the closing parenthesis.
+    pub fn max_len(&self) -> usize {
+        self.specs
+            .iter()
+            .map(|spec| spec.name.len() + 2 + spec.arity * 3 + spec.arity.saturating_sub(1))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Instruction at the very start of `bytes` along with its length in bytes. Only the
+    /// first `max_len` bytes are ever needed to decide.
+    fn match_at(&self, bytes: &[u8]) -> Option<(Instruction, usize)> {
+        self.specs.iter().find_map(|spec| {
+            if !bytes.starts_with(spec.name.as_bytes()) {
+                return None;
+            }
+
+            let mut cursor = spec.name.len();
+            let mut args = Vec::with_capacity(spec.arity);
+            expect(bytes, &mut cursor, b'(')?;
+
+            for index in 0..spec.arity {
+                if index > 0 {
+                    expect(bytes, &mut cursor, b',')?;
                 }
+                args.push(read_operand(bytes, &mut cursor)?);
             }
+
+            expect(bytes, &mut cursor, b')')?;
+            Some((
+                Instruction {
+                    name: spec.name,
+                    args,
+                },
+                cursor,
+            ))
         })
-        .collect()
+    }
 }
 
-pub fn part_a() {
-    let input = load_data().unwrap();
-    let result = parse_a(&input);
-    println!("Part a: {:?}", result);
+/// Byte offsets of a token in the source, `end` is exclusive.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
 }
 
-pub fn part_b() {
-    let input = load_data().unwrap();
-    let instructions = parse_b(&input);
-    let mut result = 0;
-    let mut execute = true;
-
-    for instruction in instructions {
-        match instruction {
-            Instruction::Mul(left, right) => {
-                if !execute {
-                    continue;
-                }
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    Instruction(Instruction),
+    Junk,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+/// Splits corrupted memory into instructions and the junk runs between them. Every byte of
+/// the input belongs to exactly one token.
+pub struct Lexer<'a> {
+    input: &'a [u8],
+    set: &'a InstructionSet,
+    position: usize,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(input: &'a str, set: &'a InstructionSet) -> Self {
+        Self {
+            input: input.as_bytes(),
+            set,
+            position: 0,
+        }
+    }
+
+    fn instruction_at(&self, start: usize) -> Option<(Instruction, usize)> {
+        self.set.match_at(&self.input[start..])
+    }
+}
+
+impl Iterator for Lexer<'_> {
+    type Item = Token;
 
-                result += left * right;
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self.position;
+        if start >= self.input.len() {
+            return None;
+        }
+
+        if let Some((instruction, len)) = self.instruction_at(start) {
+            self.position += len;
+            return Some(Token {
+                kind: TokenKind::Instruction(instruction),
+                span: Span {
+                    start,
+                    end: self.position,
+                },
+            });
+        }
+
+        let mut end = start + 1;
+        while end < self.input.len() && self.instruction_at(end).is_none() {
+            end += 1;
+        }
+        self.position = end;
+
+        Some(Token {
+            kind: TokenKind::Junk,
+            span: Span { start, end },
+        })
+    }
+}
+
+/// Operands are one to three digits, anything longer is not a valid instruction.
+fn read_operand(bytes: &[u8], cursor: &mut usize) -> Option<i32> {
+    let digits = bytes[*cursor..]
+        .iter()
+        .take_while(|b| b.is_ascii_digit())
+        .count();
+
+    if !(1..=3).contains(&digits) {
+        return None;
+    }
+
+    let value = bytes[*cursor..*cursor + digits]
+        .iter()
+        .fold(0, |acc, b| acc * 10 + (b - b'0') as i32);
+    *cursor += digits;
+
+    Some(value)
+}
+
+fn expect(bytes: &[u8], cursor: &mut usize, expected: u8) -> Option<()> {
+    if bytes.get(*cursor) == Some(&expected) {
+        *cursor += 1;
+        Some(())
+    } else {
+        None
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceEntry {
+    pub span: Span,
+    pub instruction: Instruction,
+    pub executed: bool,
+    pub state: MachineState,
+}
+
+/// Runs instructions against a `MachineState`, dispatching each one to the handler
+/// registered for it in the instruction set.
+pub struct Machine<'a> {
+    set: &'a InstructionSet,
+    state: MachineState,
+    tracing: bool,
+    trace: Vec<TraceEntry>,
+}
+
+impl<'a> Machine<'a> {
+    pub fn new(set: &'a InstructionSet) -> Self {
+        Self {
+            set,
+            state: MachineState::default(),
+            tracing: true,
+            trace: Vec::new(),
+        }
+    }
+
+    /// Machine that keeps no trace, for inputs too large to record every instruction.
+    pub fn untraced(set: &'a InstructionSet) -> Self {
+        Self {
+            tracing: false,
+            ..Self::new(set)
+        }
+    }
+
+    pub fn state(&self) -> &MachineState {
+        &self.state
+    }
+
+    pub fn trace(&self) -> &[TraceEntry] {
+        &self.trace
+    }
+
+    /// Executes a single instruction, returning whether its handler ran.
+    pub fn execute(&mut self, instruction: Instruction, span: Span) -> bool {
+        let Some(spec) = self.set.spec(instruction.name) else {
+            return false;
+        };
+
+        let executed = self.state.enabled || !spec.conditional;
+        if executed {
+            (spec.handler)(&mut self.state, &instruction.args);
+        }
+
+        if self.tracing {
+            self.trace.push(TraceEntry {
+                span,
+                instruction,
+                executed,
+                state: self.state.clone(),
+            });
+        }
+
+        executed
+    }
+
+    pub fn run(&mut self, input: &str) -> i64 {
+        for token in Lexer::new(input, self.set) {
+            if let TokenKind::Instruction(instruction) = token.kind {
+                self.execute(instruction, token.span);
             }
-            Instruction::Do => {
-                execute = true;
+        }
+
+        self.state.accumulator
+    }
+
+    /// Same as `run` but reads the memory `chunk_size` bytes at a time. The tail of each
+    /// chunk that could still be the start of an instruction is carried over to the next
+    /// one, so instructions straddling a boundary are seen whole and spans stay absolute.
+    pub fn run_reader<R: Read>(&mut self, mut reader: R, chunk_size: usize) -> Result<i64> {
+        // an empty set still needs one byte of lookahead, or the scan would run past the
+        // end of the buffer
+        let lookahead = self.set.max_len().max(1);
+        let mut chunk = vec![0; chunk_size.max(1)];
+        let mut buffer: Vec<u8> = Vec::new();
+        let mut offset = 0;
+
+        loop {
+            let read = reader.read(&mut chunk)?;
+            let eof = read == 0;
+            buffer.extend_from_slice(&chunk[..read]);
+
+            // positions from here on may need bytes we have not read yet
+            let horizon = if eof {
+                buffer.len()
+            } else {
+                (buffer.len() + 1).saturating_sub(lookahead)
+            };
+
+            let mut position = 0;
+            while position < horizon {
+                match self.set.match_at(&buffer[position..]) {
+                    Some((instruction, len)) => {
+                        let span = Span {
+                            start: offset + position,
+                            end: offset + position + len,
+                        };
+                        self.execute(instruction, span);
+                        position += len;
+                    }
+                    None => position += 1,
+                }
             }
-            Instruction::Donot => {
-                execute = false;
+
+            buffer.drain(..position);
+            offset += position;
+
+            if eof {
+                return Ok(self.state.accumulator);
             }
         }
     }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NearMissReason {
+    /// `mul[3,7]`
+    WrongBrackets,
+    /// `mul(32,64]`
+    WrongClosing,
+    /// `mul ( 2 , 4 )`
+    Whitespace,
+    /// `mul(1234,5)`
+    TooManyDigits,
+    /// `do` or `don't` not followed by `()`
+    MissingParentheses,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NearMiss {
+    pub span: Span,
+    pub reason: NearMissReason,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RegionStats {
+    pub regions: usize,
+    pub bytes: usize,
+    pub calls: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CorruptionReport {
+    pub near_misses: Vec<NearMiss>,
+    pub junk_bytes: usize,
+    pub instruction_bytes: usize,
+    pub enabled: RegionStats,
+    pub disabled: RegionStats,
+}
+
+/// Loosely parses `name ( a , b )` with any brackets, spacing or digit count at the start of
+/// `bytes`, and says what keeps it from being a valid instruction.
+fn near_miss_at(spec: &InstructionSpec, bytes: &[u8]) -> Option<(NearMissReason, usize)> {
+    if !bytes.starts_with(spec.name.as_bytes()) {
+        return None;
+    }
+
+    let skip_spaces = |cursor: &mut usize| {
+        let start = *cursor;
+        while bytes.get(*cursor) == Some(&b' ') {
+            *cursor += 1;
+        }
+        *cursor > start
+    };
+
+    let mut cursor = spec.name.len();
+    let mut spaced = skip_spaces(&mut cursor);
+    let open = match bytes.get(cursor) {
+        Some(open) if b"([{".contains(open) => *open,
+        _ if spec.arity == 0 => {
+            return Some((NearMissReason::MissingParentheses, spec.name.len()));
+        }
+        _ => return None,
+    };
+    cursor += 1;
+
+    let mut long_operand = false;
+    for index in 0..spec.arity {
+        spaced |= skip_spaces(&mut cursor);
+        if index > 0 {
+            expect(bytes, &mut cursor, b',')?;
+            spaced |= skip_spaces(&mut cursor);
+        }
+
+        let digits = bytes[cursor..]
+            .iter()
+            .take_while(|b| b.is_ascii_digit())
+            .count();
+        if digits == 0 {
+            return None;
+        }
+        long_operand |= digits > 3;
+        cursor += digits;
+    }
+
+    spaced |= skip_spaces(&mut cursor);
+    let close = *bytes.get(cursor).filter(|close| b")]}".contains(close))?;
+    cursor += 1;
+
+    let reason = if open != b'(' {
+        NearMissReason::WrongBrackets
+    } else if spaced {
+        NearMissReason::Whitespace
+    } else if long_operand {
+        NearMissReason::TooManyDigits
+    } else if close != b')' {
+        NearMissReason::WrongClosing
+    } else {
+        return None;
+    };
+
+    Some((reason, cursor))
+}
+
+fn near_misses(junk: &[u8], offset: usize, set: &InstructionSet) -> Vec<NearMiss> {
+    // longer names first so `don't` is not also reported as `do`
+    let mut specs: Vec<&InstructionSpec> = set.specs.iter().collect();
+    specs.sort_by_key(|spec| std::cmp::Reverse(spec.name.len()));
+
+    let mut found = Vec::new();
+    let mut position = 0;
+
+    while position < junk.len() {
+        let near_miss = specs
+            .iter()
+            .find_map(|spec| near_miss_at(spec, &junk[position..]));
+
+        match near_miss {
+            Some((reason, len)) => {
+                found.push(NearMiss {
+                    span: Span {
+                        start: offset + position,
+                        end: offset + position + len,
+                    },
+                    reason,
+                });
+                position += len;
+            }
+            None => position += 1,
+        }
+    }
+
+    found
+}
+
+/// Audits how much of `input` is noise: near-miss instructions hiding in the junk, and how
+/// the bytes and conditional calls split between enabled and disabled regions.
+pub fn corruption_report(input: &str, set: &InstructionSet) -> CorruptionReport {
+    let mut report = CorruptionReport::default();
+    let mut machine = Machine::untraced(set);
+    let mut region_start = 0;
+
+    for token in Lexer::new(input, set) {
+        let Span { start, end } = token.span;
+
+        let TokenKind::Instruction(instruction) = token.kind else {
+            report.junk_bytes += end - start;
+            report
+                .near_misses
+                .extend(near_misses(&input.as_bytes()[start..end], start, set));
+            continue;
+        };
+
+        report.instruction_bytes += end - start;
+        let was_enabled = machine.state().enabled;
+        let region = if was_enabled {
+            &mut report.enabled
+        } else {
+            &mut report.disabled
+        };
+
+        if set
+            .spec(instruction.name)
+            .is_some_and(|spec| spec.conditional)
+        {
+            region.calls += 1;
+        }
+
+        machine.execute(instruction, token.span);
+        if machine.state().enabled != was_enabled {
+            region.regions += 1;
+            region.bytes += end - region_start;
+            region_start = end;
+        }
+    }
+
+    if region_start < input.len() {
+        let region = if machine.state().enabled {
+            &mut report.enabled
+        } else {
+            &mut report.disabled
+        };
+        region.regions += 1;
+        region.bytes += input.len() - region_start;
+    }
+
+    report
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HighlightStyle {
+    /// Counted calls in green, suppressed calls in red and toggles in bold yellow.
+    Ansi,
+    /// `[+counted]`, `[-suppressed]` and `[!toggle]` markers for when colours are not available.
+    Plain,
+}
+
+impl HighlightStyle {
+    pub fn for_stdout() -> Self {
+        if std::io::stdout().is_terminal() {
+            HighlightStyle::Ansi
+        } else {
+            HighlightStyle::Plain
+        }
+    }
+
+    fn markers(&self, counted: bool, toggle: bool) -> (&'static str, &'static str) {
+        match (self, toggle, counted) {
+            (HighlightStyle::Ansi, true, _) => ("\x1b[1;33m", "\x1b[0m"),
+            (HighlightStyle::Ansi, false, true) => ("\x1b[32m", "\x1b[0m"),
+            (HighlightStyle::Ansi, false, false) => ("\x1b[31m", "\x1b[0m"),
+            (HighlightStyle::Plain, true, _) => ("[!", "]"),
+            (HighlightStyle::Plain, false, true) => ("[+", "]"),
+            (HighlightStyle::Plain, false, false) => ("[-", "]"),
+        }
+    }
+}
+
+/// Original `input` with every instruction wrapped in the markers of `style`, so it is easy
+/// to see which calls a preceding `don't()` suppressed.
+pub fn highlight(input: &str, set: &InstructionSet, style: HighlightStyle) -> String {
+    let mut machine = Machine::new(set);
+    machine.run(input);
+
+    let mut output = String::with_capacity(input.len() * 2);
+    let mut position = 0;
+
+    for entry in machine.trace() {
+        let Span { start, end } = entry.span;
+        let toggle = set
+            .spec(entry.instruction.name)
+            .is_some_and(|spec| !spec.conditional);
+        let (open, close) = style.markers(entry.executed, toggle);
+
+        output.push_str(&input[position..start]);
+        output.push_str(open);
+        output.push_str(&input[start..end]);
+        output.push_str(close);
+        position = end;
+    }
+
+    output.push_str(&input[position..]);
+    output
+}
+
+pub fn load_data() -> Result<String> {
+    let content = fs::read_to_string("./src/input/03.input")?;
+    Ok(content)
+}
+
+pub fn parse_a(input: &str) -> i64 {
+    let set = InstructionSet::multiply_only();
+    Machine::new(&set).run(input)
+}
+
+pub fn parse_b(input: &str) -> Vec<Instruction> {
+    let set = InstructionSet::standard();
+    Lexer::new(input, &set)
+        .filter_map(|token| match token.kind {
+            TokenKind::Instruction(instruction) => Some(instruction),
+            TokenKind::Junk => None,
+        })
+        .collect()
+}
+
+pub fn report() {
+    let input = load_data().unwrap();
+    let report = corruption_report(&input, &InstructionSet::standard());
+
+    for near_miss in report.near_misses.iter() {
+        let Span { start, end } = near_miss.span;
+        println!(
+            "{:>6}: {:<20} {:?}",
+            start,
+            &input[start..end],
+            near_miss.reason
+        );
+    }
+
+    println!(
+        "Junk bytes: {} / {}",
+        report.junk_bytes,
+        report.junk_bytes + report.instruction_bytes
+    );
+    println!("Enabled: {:?}", report.enabled);
+    println!("Disabled: {:?}", report.disabled);
+}
+
+pub fn highlight_view() {
+    let input = load_data().unwrap();
+    let set = InstructionSet::standard();
+    println!("{}", highlight(&input, &set, HighlightStyle::for_stdout()));
+}
+
+pub fn part_a() {
+    let file = File::open("./src/input/03.input").unwrap();
+    let set = InstructionSet::multiply_only();
+    let result = Machine::untraced(&set)
+        .run_reader(file, CHUNK_SIZE)
+        .unwrap();
+    println!("Part a: {:?}", result);
+}
+
+pub fn part_b() {
+    let file = File::open("./src/input/03.input").unwrap();
+    let set = InstructionSet::standard();
+    let result = Machine::untraced(&set)
+        .run_reader(file, CHUNK_SIZE)
+        .unwrap();
 
     println!("Part b: {result}");
 }
@@ -84,12 +674,250 @@ pub fn part_b() {
 mod test {
     use super::*;
 
+    #[test]
+    fn test_lexer_spans() {
+        let set = InstructionSet::standard();
+        let tokens: Vec<Token> = Lexer::new("xmul(2,4)&do()don't()", &set).collect();
+
+        assert_eq!(
+            tokens,
+            vec![
+                Token {
+                    kind: TokenKind::Junk,
+                    span: Span { start: 0, end: 1 }
+                },
+                Token {
+                    kind: TokenKind::Instruction(Instruction::new("mul", &[2, 4])),
+                    span: Span { start: 1, end: 9 }
+                },
+                Token {
+                    kind: TokenKind::Junk,
+                    span: Span { start: 9, end: 10 }
+                },
+                Token {
+                    kind: TokenKind::Instruction(Instruction::new("do", &[])),
+                    span: Span { start: 10, end: 14 }
+                },
+                Token {
+                    kind: TokenKind::Instruction(Instruction::new("don't", &[])),
+                    span: Span { start: 14, end: 21 }
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lexer_operand_digits() {
+        assert_eq!(parse_a("mul(123,456)"), 123 * 456);
+        assert_eq!(parse_a("mul(1234,5)"), 0);
+        assert_eq!(parse_a("mul(,5)"), 0);
+        assert_eq!(parse_a("mul(12,34"), 0);
+    }
+
+    #[test]
+    fn test_lexer_restarts_inside_junk() {
+        // a broken call can hide the start of a valid one
+        assert_eq!(parse_a("mul(mul(2,3)"), 6);
+        assert_eq!(parse_a("mumul(4,5)"), 20);
+    }
+
+    #[test]
+    fn test_part_a_example() {
+        let input = "xmul(2,4)%&mul[3,7]!@^do_not_mul(5,5)+mul(32,64]then(mul(11,8)mul(8,5))";
+        assert_eq!(parse_a(input), 161);
+    }
+
+    #[test]
+    fn test_machine_trace() {
+        let set = InstructionSet::standard();
+        let mut machine = Machine::new(&set);
+        let input = "xmul(2,4)&mul[3,7]!^don't()_mul(5,5)+mul(32,64](mul(11,8)undo()?mul(8,5))";
+
+        assert_eq!(machine.run(input), 48);
+        assert_eq!(
+            machine
+                .trace()
+                .iter()
+                .map(|entry| (entry.instruction.name, entry.executed))
+                .collect::<Vec<_>>(),
+            vec![
+                ("mul", true),
+                ("don't", true),
+                ("mul", false),
+                ("mul", false),
+                ("do", true),
+                ("mul", true),
+            ]
+        );
+        assert_eq!(
+            machine.trace()[1].state,
+            MachineState {
+                enabled: false,
+                accumulator: 8
+            }
+        );
+    }
+
+    #[test]
+    fn test_machine_extended_instructions() {
+        let set = InstructionSet::standard()
+            .register(InstructionSpec {
+                name: "add",
+                arity: 2,
+                conditional: true,
+                handler: |state, args| state.accumulator += (args[0] + args[1]) as i64,
+            })
+            .register(InstructionSpec {
+                name: "sub",
+                arity: 2,
+                conditional: true,
+                handler: |state, args| state.accumulator -= (args[0] + args[1]) as i64,
+            })
+            .register(InstructionSpec {
+                name: "toggle",
+                arity: 0,
+                conditional: false,
+                handler: |state, _| state.enabled = !state.enabled,
+            });
+
+        let input = "add(1,2)mul(2,3)toggle()add(100,1)toggle()sub(4,5)";
+        assert_eq!(Machine::new(&set).run(input), 3 + 6 - 9);
+    }
+
+    #[test]
+    fn test_stream_matches_whole_input_at_any_chunk_size() {
+        let set = InstructionSet::standard();
+        let input = "xmul(2,4)&mul[3,7]!^don't()_mul(5,5)+mul(32,64](mul(11,8)undo()?mul(8,5))\
+                     mul(123,456)don't()mul(1234,5)do()mumul(7,8)mul(9,9";
+
+        let mut whole = Machine::new(&set);
+        whole.run(input);
+
+        for chunk_size in 1..=input.len() + 1 {
+            let mut streamed = Machine::new(&set);
+            streamed.run_reader(input.as_bytes(), chunk_size).unwrap();
+
+            assert_eq!(streamed.state(), whole.state(), "chunk size {chunk_size}");
+            assert_eq!(streamed.trace(), whole.trace(), "chunk size {chunk_size}");
+        }
+    }
+
+    #[test]
+    fn test_stream_keeps_state_across_chunks() {
+        let set = InstructionSet::standard();
+        let input = "don't()mul(2,2)do()mul(3,3)";
+
+        for chunk_size in [1, 2, 5, 7, 8, 13] {
+            let mut machine = Machine::untraced(&set);
+            assert_eq!(machine.run_reader(input.as_bytes(), chunk_size).unwrap(), 9);
+        }
+    }
+
+    #[test]
+    fn test_stream_with_empty_set() {
+        let set = InstructionSet::new();
+        let input = "mul(2,3)do()";
+
+        for chunk_size in 1..=input.len() + 1 {
+            let mut machine = Machine::new(&set);
+            assert_eq!(machine.run_reader(input.as_bytes(), chunk_size).unwrap(), 0);
+            assert!(machine.trace().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_stream_real_input() {
+        let input = load_data().unwrap();
+        let set = InstructionSet::standard();
+        let expected = Machine::untraced(&set).run(&input);
+
+        for chunk_size in [1, 3, 11, 12, 13, 97, 4096, CHUNK_SIZE] {
+            let mut machine = Machine::untraced(&set);
+            let result = machine.run_reader(input.as_bytes(), chunk_size).unwrap();
+            assert_eq!(result, expected, "chunk size {chunk_size}");
+        }
+    }
+
+    #[test]
+    fn test_near_misses() {
+        let input = "xmul(2,4)&mul[3,7]!^don't()_mul(5,5)+mul(32,64](mul(11,8)undo()?\
+                     mul ( 2 , 4 )mul(1234,5)do_not_don't mul(8,5))";
+        let report = corruption_report(input, &InstructionSet::standard());
+
+        assert_eq!(
+            report
+                .near_misses
+                .iter()
+                .map(|near_miss| (
+                    &input[near_miss.span.start..near_miss.span.end],
+                    near_miss.reason
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                ("mul[3,7]", NearMissReason::WrongBrackets),
+                ("mul(32,64]", NearMissReason::WrongClosing),
+                ("mul ( 2 , 4 )", NearMissReason::Whitespace),
+                ("mul(1234,5)", NearMissReason::TooManyDigits),
+                ("do", NearMissReason::MissingParentheses),
+                ("don't", NearMissReason::MissingParentheses),
+            ]
+        );
+        assert_eq!(report.near_misses[0].span, Span { start: 10, end: 18 });
+    }
+
+    #[test]
+    fn test_region_stats() {
+        let input = "mul(2,4)xx don't()mul(5,5)__do()mul(8,5)";
+        let report = corruption_report(input, &InstructionSet::standard());
+
+        assert_eq!(report.instruction_bytes, 8 + 7 + 8 + 4 + 8);
+        assert_eq!(report.junk_bytes, input.len() - report.instruction_bytes);
+        assert_eq!(
+            report.enabled,
+            RegionStats {
+                regions: 2,
+                bytes: 18 + 8,
+                calls: 2
+            }
+        );
+        assert_eq!(
+            report.disabled,
+            RegionStats {
+                regions: 1,
+                bytes: 14,
+                calls: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_highlight_plain() {
+        let input = "xmul(2,4)&mul[3,7]!^don't()_mul(5,5)+mul(32,64](mul(11,8)undo()?mul(8,5))";
+        let set = InstructionSet::standard();
+
+        assert_eq!(
+            highlight(input, &set, HighlightStyle::Plain),
+            "x[+mul(2,4)]&mul[3,7]!^[!don't()]_[-mul(5,5)]+mul(32,64]([-mul(11,8)]un[!do()]?\
+             [+mul(8,5)])"
+        );
+    }
+
+    #[test]
+    fn test_highlight_ansi() {
+        let set = InstructionSet::standard();
+
+        assert_eq!(
+            highlight("mul(1,2)don't()mul(3,4)", &set, HighlightStyle::Ansi),
+            "\x1b[32mmul(1,2)\x1b[0m\x1b[1;33mdon't()\x1b[0m\x1b[31mmul(3,4)\x1b[0m"
+        );
+    }
+
     #[test]
     fn test_part_b_can_parse_do() {
         let input = "do()";
         let instructions = parse_b(input);
 
-        assert_eq!(instructions, vec![Instruction::Do]);
+        assert_eq!(instructions, vec![Instruction::new("do", &[])]);
     }
 
     #[test]
@@ -97,7 +925,7 @@ mod test {
         let input = "don't()";
         let instructions = parse_b(input);
 
-        assert_eq!(instructions, vec![Instruction::Donot]);
+        assert_eq!(instructions, vec![Instruction::new("don't", &[])]);
     }
 
     #[test]
@@ -108,10 +936,10 @@ mod test {
         assert_eq!(
             instructions,
             vec![
-                Instruction::Mul(2, 3),
-                Instruction::Do,
-                Instruction::Mul(4, 5),
-                Instruction::Donot,
+                Instruction::new("mul", &[2, 3]),
+                Instruction::new("do", &[]),
+                Instruction::new("mul", &[4, 5]),
+                Instruction::new("don't", &[]),
             ]
         );
     }
@@ -124,12 +952,12 @@ mod test {
         assert_eq!(
             instructions,
             vec![
-                Instruction::Mul(2, 4),
-                Instruction::Donot,
-                Instruction::Mul(5, 5),
-                Instruction::Mul(11, 8),
-                Instruction::Do,
-                Instruction::Mul(8, 5),
+                Instruction::new("mul", &[2, 4]),
+                Instruction::new("don't", &[]),
+                Instruction::new("mul", &[5, 5]),
+                Instruction::new("mul", &[11, 8]),
+                Instruction::new("do", &[]),
+                Instruction::new("mul", &[8, 5]),
             ]
         );
     }
@@ -1,122 +1,594 @@
-use anyhow::Result;
-use std::{collections::HashMap, fs};
+use anyhow::{bail, Result};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs,
+    str::FromStr,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Direction {
+    North,
+    NorthEast,
+    East,
+    SouthEast,
+    South,
+    SouthWest,
+    West,
+    NorthWest,
+}
+
+impl Direction {
+    pub const ALL: [Direction; 8] = [
+        Direction::North,
+        Direction::NorthEast,
+        Direction::East,
+        Direction::SouthEast,
+        Direction::South,
+        Direction::SouthWest,
+        Direction::West,
+        Direction::NorthWest,
+    ];
+
+    /// (row, col) offset of one step in this direction.
+    pub fn delta(&self) -> (isize, isize) {
+        match self {
+            Direction::North => (-1, 0),
+            Direction::NorthEast => (-1, 1),
+            Direction::East => (0, 1),
+            Direction::SouthEast => (1, 1),
+            Direction::South => (1, 0),
+            Direction::SouthWest => (1, -1),
+            Direction::West => (0, -1),
+            Direction::NorthWest => (-1, -1),
+        }
+    }
+
+    pub fn opposite(&self) -> Direction {
+        match self {
+            Direction::North => Direction::South,
+            Direction::NorthEast => Direction::SouthWest,
+            Direction::East => Direction::West,
+            Direction::SouthEast => Direction::NorthWest,
+            Direction::South => Direction::North,
+            Direction::SouthWest => Direction::NorthEast,
+            Direction::West => Direction::East,
+            Direction::NorthWest => Direction::SouthEast,
+        }
+    }
+}
+
+/// What happens when a word or pattern runs past the edge of the board.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Boundary {
+    #[default]
+    Bounded,
+    WrapHorizontal,
+    WrapVertical,
+    Torus,
+}
+
+impl Boundary {
+    fn wraps_rows(&self) -> bool {
+        matches!(self, Boundary::WrapVertical | Boundary::Torus)
+    }
+
+    fn wraps_cols(&self) -> bool {
+        matches!(self, Boundary::WrapHorizontal | Boundary::Torus)
+    }
+}
+
+/// Moves `value` by `delta` within `0..size`, wrapping around when `wraps` is set.
+fn shift(value: usize, delta: isize, size: usize, wraps: bool) -> Option<usize> {
+    if wraps {
+        Some((value as isize + delta).rem_euclid(size as isize) as usize)
+    } else {
+        value
+            .checked_add_signed(delta)
+            .filter(|value| *value < size)
+    }
+}
+
+/// Cells read in one direction. Cyclic lines come back to their first cell after the last
+/// one, which only happens when the board wraps.
+struct Line {
+    cells: Vec<(usize, usize)>,
+    cyclic: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct WordMatch {
+    pub start: (usize, usize),
+    pub direction: Direction,
+    pub word: String,
+}
+
+/// Aho-Corasick automaton over a word list, so every line of the board is scanned once no
+/// matter how many words we are looking for.
+struct WordAutomaton {
+    goto: Vec<HashMap<char, usize>>,
+    fail: Vec<usize>,
+    /// Words (by index) that end at each node, including those reached via fail links.
+    output: Vec<Vec<usize>>,
+    lengths: Vec<usize>,
+}
+
+impl WordAutomaton {
+    fn new(words: &[&str]) -> Self {
+        let mut goto = vec![HashMap::new()];
+        let mut output = vec![Vec::new()];
+
+        for (index, word) in words.iter().enumerate() {
+            if word.is_empty() {
+                continue;
+            }
+
+            let mut node = 0;
+            for ch in word.chars() {
+                node = match goto[node].get(&ch) {
+                    Some(next) => *next,
+                    None => {
+                        goto.push(HashMap::new());
+                        output.push(Vec::new());
+                        let next = goto.len() - 1;
+                        goto[node].insert(ch, next);
+                        next
+                    }
+                };
+            }
+            output[node].push(index);
+        }
+
+        let mut fail = vec![0; goto.len()];
+        let mut queue: VecDeque<usize> = goto[0].values().copied().collect();
+
+        while let Some(node) = queue.pop_front() {
+            let children: Vec<(char, usize)> = goto[node].iter().map(|(c, n)| (*c, *n)).collect();
+
+            for (ch, child) in children {
+                let mut fallback = fail[node];
+                while fallback != 0 && !goto[fallback].contains_key(&ch) {
+                    fallback = fail[fallback];
+                }
+
+                fail[child] = match goto[fallback].get(&ch) {
+                    Some(next) if *next != child => *next,
+                    _ => 0,
+                };
+
+                let inherited = output[fail[child]].clone();
+                output[child].extend(inherited);
+                queue.push_back(child);
+            }
+        }
+
+        Self {
+            goto,
+            fail,
+            output,
+            lengths: words.iter().map(|word| word.chars().count()).collect(),
+        }
+    }
+
+    fn next(&self, mut node: usize, ch: char) -> usize {
+        loop {
+            if let Some(next) = self.goto[node].get(&ch) {
+                return *next;
+            }
+            if node == 0 {
+                return 0;
+            }
+            node = self.fail[node];
+        }
+    }
+
+    fn max_len(&self) -> usize {
+        self.lengths.iter().copied().max().unwrap_or(0)
+    }
+
+    /// Calls `on_match(word, start)` for every word found in `text`, `start` being the
+    /// position of its first char.
+    fn scan(&self, text: &[char], mut on_match: impl FnMut(usize, usize)) {
+        let mut node = 0;
+
+        for (position, ch) in text.iter().enumerate() {
+            node = self.next(node, *ch);
+            for word in self.output[node].iter() {
+                on_match(*word, position + 1 - self.lengths[*word]);
+            }
+        }
+    }
+}
+
+/// A 2D mask of chars where `.` matches anything, e.g. the X-MAS shape
+/// `["M.S", ".A.", "M.S"]`. Rows and columns of wildcards around the edge are trimmed, so a
+/// padded mask is the same pattern as the bare one.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Pattern {
+    pub rows: usize,
+    pub cols: usize,
+    /// (row, col) offsets of the non-wildcard cells, sorted.
+    pub cells: Vec<((usize, usize), char)>,
+}
+
+impl Pattern {
+    pub fn new(lines: &[&str]) -> Self {
+        let cells = lines
+            .iter()
+            .enumerate()
+            .flat_map(|(row, line)| {
+                line.chars()
+                    .enumerate()
+                    .filter(|(_, ch)| *ch != '.')
+                    .map(move |(col, ch)| ((row, col), ch))
+            })
+            .collect();
+
+        Self::from_cells(cells)
+    }
+
+    fn from_cells(cells: Vec<((usize, usize), char)>) -> Self {
+        let top = cells.iter().map(|((row, _), _)| *row).min().unwrap_or(0);
+        let left = cells.iter().map(|((_, col), _)| *col).min().unwrap_or(0);
+        let mut cells: Vec<((usize, usize), char)> = cells
+            .into_iter()
+            .map(|((row, col), ch)| ((row - top, col - left), ch))
+            .collect();
+        cells.sort();
+
+        let rows = cells.iter().map(|((row, _), _)| row + 1).max().unwrap_or(0);
+        let cols = cells.iter().map(|((_, col), _)| col + 1).max().unwrap_or(0);
+
+        Self { rows, cols, cells }
+    }
+
+    /// Quarter turn clockwise.
+    pub fn rotated(&self) -> Self {
+        let cells = self
+            .cells
+            .iter()
+            .map(|((row, col), ch)| ((*col, self.rows - 1 - row), *ch))
+            .collect();
+
+        Self::from_cells(cells)
+    }
+
+    /// Mirror image across the vertical axis.
+    pub fn reflected(&self) -> Self {
+        let cells = self
+            .cells
+            .iter()
+            .map(|((row, col), ch)| ((*row, self.cols - 1 - col), *ch))
+            .collect();
+
+        Self::from_cells(cells)
+    }
+
+    /// All rotations and reflections, keeping only one copy of those that look the same.
+    pub fn variants(&self) -> Vec<Pattern> {
+        let mut variants: Vec<Pattern> = Vec::with_capacity(8);
+        let mut current = self.clone();
+
+        for _ in 0..4 {
+            for candidate in [current.clone(), current.reflected()] {
+                if !variants.contains(&candidate) {
+                    variants.push(candidate);
+                }
+            }
+            current = current.rotated();
+        }
+
+        variants
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatternMatch {
+    pub origin: (usize, usize),
+    pub pattern: Pattern,
+    pub cells: Vec<(usize, usize)>,
+}
 
 #[derive(Debug, Clone)]
-struct Board {
+pub struct Board {
     rows: usize,
     cols: usize,
     data: HashMap<(usize, usize), char>,
+    boundary: Boundary,
 }
 
 impl Board {
     pub fn new(rows: usize, cols: usize, data: HashMap<(usize, usize), char>) -> Self {
-        Self { rows, cols, data }
+        Self {
+            rows,
+            cols,
+            data,
+            boundary: Boundary::Bounded,
+        }
+    }
+
+    pub fn with_boundary(mut self, boundary: Boundary) -> Self {
+        self.boundary = boundary;
+        self
     }
 
+    #[allow(dead_code)]
     pub fn print(&self) {
         for (row, col) in self.data.keys() {
             println!("{} {} {}", row, col, self.data[&(*row, *col)]);
         }
     }
 
-    pub fn counts_a(&self) -> usize {
-        let mut count = 0;
+    /// Cells covered by a word match, in reading order.
+    pub fn word_cells(&self, found: &WordMatch) -> Vec<(usize, usize)> {
+        let mut cells = vec![found.start];
+
+        for _ in 1..found.word.chars().count() {
+            match self.step(cells[cells.len() - 1], found.direction) {
+                Some(next) => cells.push(next),
+                None => break,
+            }
+        }
+
+        cells
+    }
+
+    /// How many matches cover each cell. A match that wraps onto the same cell more than
+    /// once still only counts once there.
+    pub fn coverage(&self, matches: &[Vec<(usize, usize)>]) -> Vec<Vec<usize>> {
+        let mut counts = vec![vec![0; self.cols]; self.rows];
+
+        for cells in matches {
+            let mut cells = cells.clone();
+            cells.sort();
+            cells.dedup();
+
+            for (row, col) in cells {
+                counts[row][col] += 1;
+            }
+        }
+
+        counts
+    }
+
+    /// The board with only the cells covered by a match shown, like the puzzle illustration.
+    pub fn render_highlight(&self, matches: &[Vec<(usize, usize)>]) -> String {
+        let counts = self.coverage(matches);
+        self.render(|cell| match counts[cell.0][cell.1] {
+            0 => '.',
+            _ => self.data[&cell],
+        })
+    }
+
+    /// Number of matches covering each cell, `.` for none and `+` for more than nine.
+    pub fn render_heatmap(&self, matches: &[Vec<(usize, usize)>]) -> String {
+        let counts = self.coverage(matches);
+        self.render(|cell| match counts[cell.0][cell.1] {
+            0 => '.',
+            count @ 1..=9 => char::from_digit(count as u32, 10).unwrap(),
+            _ => '+',
+        })
+    }
+
+    fn render(&self, symbol: impl Fn((usize, usize)) -> char) -> String {
+        let mut output = String::with_capacity((self.cols + 1) * self.rows);
 
         for row in 0..self.rows {
             for col in 0..self.cols {
-                let values = self.square_values_a(row, col);
+                output.push(symbol((row, col)));
+            }
+            output.push('\n');
+        }
 
-                count += values
-                    .iter()
-                    .filter(|v| *v == "XMAS" || *v == "SAMX")
-                    .count();
+        output
+    }
+
+    pub fn counts_a(&self) -> usize {
+        self.find_words(&["XMAS"]).len()
+    }
+
+    /// Every occurrence of every word, reading in all 8 directions.
+    pub fn find_words(&self, words: &[&str]) -> Vec<WordMatch> {
+        let automaton = WordAutomaton::new(words);
+        let mut matches = Vec::new();
+
+        for direction in Direction::ALL {
+            for line in self.lines(direction) {
+                let len = line.cells.len();
+
+                // a cyclic line is read round and round until every word could have started
+                // on each of its cells, but only the first lap of starts is kept
+                let text: Vec<char> = if line.cyclic {
+                    (0..len + automaton.max_len().saturating_sub(1))
+                        .map(|index| self.data[&line.cells[index % len]])
+                        .collect()
+                } else {
+                    line.cells.iter().map(|cell| self.data[cell]).collect()
+                };
+
+                automaton.scan(&text, |word, start| {
+                    if start < len {
+                        matches.push(WordMatch {
+                            start: line.cells[start],
+                            direction,
+                            word: words[word].to_string(),
+                        });
+                    }
+                });
             }
         }
 
-        count
+        // On a wrapped board one cell high or wide, or two cells along the wrapped axis,
+        // several directions walk the very same cells. Keep one match per occurrence, the
+        // one whose direction comes first in `Direction::ALL`.
+        let mut seen = HashSet::new();
+        matches.retain(|found| seen.insert((found.word.clone(), self.word_cells(found))));
+
+        matches
     }
 
-    pub fn counts_b(&self) -> usize {
-        let mut count = 0;
+    fn step(&self, (row, col): (usize, usize), direction: Direction) -> Option<(usize, usize)> {
+        let (d_row, d_col) = direction.delta();
+        let row = shift(row, d_row, self.rows, self.boundary.wraps_rows())?;
+        let col = shift(col, d_col, self.cols, self.boundary.wraps_cols())?;
+        Some((row, col))
+    }
+
+    /// Cells read in `direction`, split into lines that each start at a cell with nothing
+    /// before it. Cells left over after that can only be on cycles.
+    fn lines(&self, direction: Direction) -> Vec<Line> {
+        let mut lines = Vec::new();
+        let mut seen = vec![vec![false; self.cols]; self.rows];
+
+        let walk = |start: (usize, usize), seen: &mut Vec<Vec<bool>>| {
+            let mut cells = vec![start];
+            seen[start.0][start.1] = true;
+
+            while let Some(next) = self.step(cells[cells.len() - 1], direction) {
+                if next == start {
+                    return Line {
+                        cells,
+                        cyclic: true,
+                    };
+                }
+                seen[next.0][next.1] = true;
+                cells.push(next);
+            }
+
+            Line {
+                cells,
+                cyclic: false,
+            }
+        };
 
         for row in 0..self.rows {
             for col in 0..self.cols {
-                let values = self.diag_values_b(row, col);
-                match (values[0].as_str(), values[1].as_str()) {
-                    ("MAS", "MAS") | ("SAM", "SAM") | ("MAS", "SAM") | ("SAM", "MAS") => {
-                        count += 1;
-                    }
-                    _ => {}
+                if self.step((row, col), direction.opposite()).is_none() {
+                    lines.push(walk((row, col), &mut seen));
+                }
+            }
+        }
+
+        for row in 0..self.rows {
+            for col in 0..self.cols {
+                if !seen[row][col] {
+                    lines.push(walk((row, col), &mut seen));
                 }
             }
         }
 
-        count
+        lines
+    }
+
+    pub fn counts_b(&self) -> usize {
+        let x_mas = Pattern::new(&["M.S", ".A.", "M.S"]);
+        self.find_pattern(&x_mas, true).len()
     }
 
-    pub fn diag_values_b(&self, row: usize, col: usize) -> [String; 2] {
-        let first = [(row, col), (row + 1, col + 1), (row + 2, col + 2)];
-        let second = [(row + 2, col), (row + 1, col + 1), (row, col + 2)];
+    /// Every placement of `pattern` on the board. With `symmetric`, all distinct rotations
+    /// and reflections of the pattern are tried as well.
+    pub fn find_pattern(&self, pattern: &Pattern, symmetric: bool) -> Vec<PatternMatch> {
+        let variants = if symmetric {
+            pattern.variants()
+        } else {
+            vec![pattern.clone()]
+        };
+
+        let mut matches = Vec::new();
+
+        for variant in variants {
+            for row in 0..self.rows {
+                for col in 0..self.cols {
+                    let cells: Option<Vec<(usize, usize)>> = variant
+                        .cells
+                        .iter()
+                        .map(|(offset, ch)| {
+                            self.offset((row, col), *offset)
+                                .filter(|cell| self.data[cell] == *ch)
+                        })
+                        .collect();
+
+                    if let Some(cells) = cells {
+                        matches.push(PatternMatch {
+                            origin: (row, col),
+                            pattern: variant.clone(),
+                            cells,
+                        });
+                    }
+                }
+            }
+        }
 
-        [self.value(first), self.value(second)]
+        matches
     }
 
-    fn value(&self, coords: [(usize, usize); 3]) -> String {
-        String::from(
-            coords
-                .iter()
-                .map(|(r, c)| self.data.get(&(*r, *c)).unwrap_or(&'*'))
-                .collect::<String>(),
-        )
+    fn offset(
+        &self,
+        (row, col): (usize, usize),
+        (d_row, d_col): (usize, usize),
+    ) -> Option<(usize, usize)> {
+        let row = shift(row, d_row as isize, self.rows, self.boundary.wraps_rows())?;
+        let col = shift(col, d_col as isize, self.cols, self.boundary.wraps_cols())?;
+        Some((row, col))
     }
+}
 
-    pub fn square_values_a(&self, row: usize, col: usize) -> Vec<String> {
-        let mut coords = vec![];
+impl FromStr for Board {
+    type Err = anyhow::Error;
 
-        coords.push([(row, col), (row, col + 1), (row, col + 2), (row, col + 3)]);
-        coords.push([(row, col), (row + 1, col), (row + 2, col), (row + 3, col)]);
-        coords.push([
-            (row, col),
-            (row + 1, col + 1),
-            (row + 2, col + 2),
-            (row + 3, col + 3),
-        ]);
-        coords.push([
-            (row + 3, col),
-            (row + 2, col + 1),
-            (row + 1, col + 2),
-            (row, col + 3),
-        ]);
+    fn from_str(s: &str) -> Result<Self> {
+        let mut data = HashMap::new();
+        let rows = s.lines().count();
+        let cols = s.lines().next().map_or(0, |line| line.chars().count());
 
-        let values = coords
-            .iter()
-            .map(|coord| {
-                String::from(
-                    coord
-                        .iter()
-                        .map(|(r, c)| self.data.get(&(*r, *c)).unwrap_or(&'*'))
-                        .collect::<String>(),
-                )
-            })
-            .collect();
+        for (row, line) in s.lines().enumerate() {
+            let len = line.chars().count();
+            if len < cols {
+                bail!(
+                    "row {row} ends early at {:?}, expected {cols} chars like row 0",
+                    (row, len)
+                );
+            }
+            if len > cols {
+                bail!(
+                    "row {row} runs over at {:?}, expected {cols} chars like row 0",
+                    (row, cols)
+                );
+            }
 
-        values
+            for (col, val) in line.chars().enumerate() {
+                data.insert((row, col), val);
+            }
+        }
+
+        Ok(Board::new(rows, cols, data))
     }
 }
 
 pub fn load_data() -> Result<Board> {
     let content = fs::read_to_string("./src/input/04.input")?;
-    let mut data = HashMap::new();
-    let rows = content.lines().count();
-    let cols = content.lines().next().unwrap().len();
+    Board::from_str(&content)
+}
 
-    for (row, line) in content.lines().enumerate() {
-        for (col, val) in line.chars().enumerate() {
-            data.insert((row, col), val);
-        }
-    }
+/// Prints the XMAS matches of the input the way the puzzle illustrates them, or writes them
+/// to `path` when given.
+pub fn render_xmas(heatmap: bool, path: Option<&str>) {
+    let board = load_data().unwrap();
+    let matches: Vec<Vec<(usize, usize)>> = board
+        .find_words(&["XMAS"])
+        .iter()
+        .map(|found| board.word_cells(found))
+        .collect();
+
+    let output = if heatmap {
+        board.render_heatmap(&matches)
+    } else {
+        board.render_highlight(&matches)
+    };
 
-    Ok(Board::new(rows, cols, data))
+    match path {
+        Some(path) => fs::write(path, output).unwrap(),
+        None => print!("{output}"),
+    }
 }
 
 pub fn part_a() {
@@ -133,9 +605,361 @@ pub fn part_b() {
 mod test {
     use super::*;
 
+    fn small_board() -> Board {
+        let content = fs::read_to_string("./src/input/04_sm.input").unwrap();
+        Board::from_str(&content).unwrap()
+    }
+
+    /// Reads each word from every cell in every direction.
+    fn find_words_brute(board: &Board, words: &[&str]) -> Vec<WordMatch> {
+        let mut matches = Vec::new();
+        let mut seen = HashSet::new();
+
+        for row in 0..board.rows {
+            for col in 0..board.cols {
+                for direction in Direction::ALL {
+                    for word in words.iter().filter(|word| !word.is_empty()) {
+                        let chars: Vec<char> = word.chars().collect();
+                        let mut cell = Some((row, col));
+                        let mut matched = 0;
+                        let mut walked = Vec::new();
+
+                        while let Some(current) = cell {
+                            if board.data[&current] != chars[matched] {
+                                break;
+                            }
+                            walked.push(current);
+                            matched += 1;
+                            if matched == chars.len() {
+                                break;
+                            }
+                            cell = board.step(current, direction);
+                        }
+
+                        if matched == chars.len() && seen.insert((word.to_string(), walked)) {
+                            matches.push(WordMatch {
+                                start: (row, col),
+                                direction,
+                                word: word.to_string(),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        matches.sort();
+        matches
+    }
+
     #[test]
     fn test_board() {
         let board = load_data().unwrap();
         println!("{:?}", board.counts_b());
     }
+
+    #[test]
+    fn test_counts_b_small() {
+        assert_eq!(small_board().counts_b(), 9);
+    }
+
+    #[test]
+    fn test_pattern_variants() {
+        let x_mas = Pattern::new(&["M.S", ".A.", "M.S"]);
+        assert_eq!(x_mas.variants().len(), 4);
+
+        let plus = Pattern::new(&[".M.", "MAS", ".S."]);
+        assert_eq!(plus.variants().len(), 4);
+
+        let block = Pattern::new(&["XXX", "XXX", "XXX"]);
+        assert_eq!(block.variants(), vec![block.clone()]);
+
+        let corner = Pattern::new(&["XM", "A."]);
+        assert_eq!(corner.variants().len(), 8);
+        assert_eq!(corner.rotated().rotated().rotated().rotated(), corner);
+        assert_eq!(corner.reflected().reflected(), corner);
+    }
+
+    #[test]
+    fn test_ragged_board_is_rejected() {
+        assert_eq!(
+            Board::from_str("XMAS\nXM").unwrap_err().to_string(),
+            "row 1 ends early at (1, 2), expected 4 chars like row 0"
+        );
+        assert_eq!(
+            Board::from_str("XMAS\n\nXMAS").unwrap_err().to_string(),
+            "row 1 ends early at (1, 0), expected 4 chars like row 0"
+        );
+        assert_eq!(
+            Board::from_str("XM\nXMAS").unwrap_err().to_string(),
+            "row 1 runs over at (1, 2), expected 2 chars like row 0"
+        );
+        assert!(Board::from_str("XMAS\nSAMX\n").is_ok());
+    }
+
+    #[test]
+    fn test_padded_pattern() {
+        let bare = Pattern::new(&["XMAS"]);
+        let padded = Pattern::new(&["XMAS", "...."]);
+        assert_eq!(padded, bare);
+        assert_eq!(
+            Pattern::new(&["....", ".XM.", "...."]),
+            Pattern::new(&["XM"])
+        );
+        assert_eq!(padded.variants().len(), 4);
+
+        let board = small_board();
+        assert_eq!(
+            board.find_pattern(&padded, true),
+            board.find_pattern(&bare, true)
+        );
+        assert_eq!(board.find_pattern(&padded, true).len(), 8);
+    }
+
+    #[test]
+    fn test_find_pattern() {
+        let board = Board::from_str("M.S.\n.A.M\nM.SA\n..MS").unwrap();
+        let x_mas = Pattern::new(&["M.S", ".A.", "M.S"]);
+
+        assert_eq!(board.find_pattern(&x_mas, false).len(), 1);
+        assert_eq!(board.find_pattern(&x_mas, true).len(), 1);
+
+        let matched = &board.find_pattern(&x_mas, true)[0];
+        assert_eq!(matched.origin, (0, 0));
+        assert_eq!(matched.cells, vec![(0, 0), (0, 2), (1, 1), (2, 0), (2, 2)]);
+
+        // the only MAS on the board reads downwards
+        let row = Pattern::new(&["MAS"]);
+        assert_eq!(board.find_pattern(&row, false).len(), 0);
+        assert_eq!(board.find_pattern(&row, true).len(), 1);
+        assert_eq!(
+            board.find_pattern(&row, true)[0].cells,
+            vec![(1, 3), (2, 3), (3, 3)]
+        );
+    }
+
+    #[test]
+    fn test_find_pattern_symmetric_block() {
+        let board = Board::from_str("XXXX\nXXXX\nXXXX").unwrap();
+        let block = Pattern::new(&["XXX", "XXX", "XXX"]);
+
+        assert_eq!(board.find_pattern(&block, true).len(), 2);
+    }
+
+    #[test]
+    fn test_wrapped_words() {
+        let board = Board::from_str("ABC").unwrap();
+        assert_eq!(board.find_words(&["CAB"]).len(), 0);
+
+        let board = board.with_boundary(Boundary::WrapHorizontal);
+        assert_eq!(
+            board.find_words(&["CAB"]),
+            vec![WordMatch {
+                start: (0, 2),
+                direction: Direction::East,
+                word: "CAB".into()
+            }]
+        );
+
+        // longer than the row, but it can only start in one place
+        assert_eq!(board.find_words(&["ABCABCA"]).len(), 1);
+        assert_eq!(board.find_words(&["ACBACBA"]).len(), 1);
+    }
+
+    #[test]
+    fn test_torus_diagonals() {
+        let board = Board::from_str("XS\nAM")
+            .unwrap()
+            .with_boundary(Boundary::Torus);
+
+        // on a 2x2 torus the south-east diagonal from X is X M X M ..., and so are the
+        // other three diagonals, which walk the same cells
+        assert_eq!(
+            board.find_words(&["XMXMX"]),
+            vec![WordMatch {
+                start: (0, 0),
+                direction: Direction::NorthEast,
+                word: "XMXMX".into()
+            }]
+        );
+
+        let board = Board::from_str("XMAS")
+            .unwrap()
+            .with_boundary(Boundary::WrapVertical);
+        assert_eq!(
+            board.find_words(&["XMAS"]),
+            vec![WordMatch {
+                start: (0, 0),
+                direction: Direction::NorthEast,
+                word: "XMAS".into()
+            }]
+        );
+
+        let board = Board::from_str("XM")
+            .unwrap()
+            .with_boundary(Boundary::Torus);
+        assert_eq!(board.find_words(&["XM"]).len(), 1);
+        assert_eq!(board.find_words(&["X"]).len(), 1);
+    }
+
+    #[test]
+    fn test_find_words_matches_brute_force_all_boundaries() {
+        let board = small_board();
+        let words = [
+            "XMAS",
+            "SAMX",
+            "MM",
+            "AXM",
+            "SMSMS",
+            "MSAMXMSMSAM",
+            "XMASAMXMAXMASAMX",
+            "MMMSXXMASMMMMSXXMASMM",
+        ];
+
+        let tiny = ["XMAS", "XS\nAM", "XM", "X", "XM\nAS\nMX"];
+        let tiny_words = ["X", "XM", "XMAS", "XMXMX", "SAMX", "MXMX", "XAX"];
+        let boards = std::iter::once((board, &words[..])).chain(
+            tiny.iter()
+                .map(|text| (Board::from_str(text).unwrap(), &tiny_words[..])),
+        );
+
+        for (board, words) in boards {
+            for boundary in [
+                Boundary::Bounded,
+                Boundary::WrapHorizontal,
+                Boundary::WrapVertical,
+                Boundary::Torus,
+            ] {
+                let board = board.clone().with_boundary(boundary);
+                let mut matches = board.find_words(words);
+                matches.sort();
+
+                assert_eq!(matches, find_words_brute(&board, words), "{:?}", boundary);
+            }
+        }
+    }
+
+    #[test]
+    fn test_wrapped_pattern() {
+        let x_mas = Pattern::new(&["M.S", ".A.", "M.S"]);
+        let board = Board::from_str(".A.\nS.M\nS.M").unwrap();
+        assert_eq!(board.find_pattern(&x_mas, true).len(), 0);
+
+        // the A in the top row sits between the two bottom rows once the board wraps
+        let board = board.with_boundary(Boundary::WrapVertical);
+        let matches = board.find_pattern(&x_mas, true);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].origin, (2, 0));
+        assert_eq!(
+            matches[0].cells,
+            vec![(2, 0), (2, 2), (0, 1), (1, 0), (1, 2)]
+        );
+    }
+
+    #[test]
+    fn test_render_highlight() {
+        let board = small_board();
+        let matches: Vec<Vec<(usize, usize)>> = board
+            .find_words(&["XMAS"])
+            .iter()
+            .map(|found| board.word_cells(found))
+            .collect();
+
+        assert_eq!(
+            board.render_highlight(&matches),
+            "....XXMAS.\n\
+             .SAMXMS...\n\
+             ...S..A...\n\
+             ..A.A.MS.X\n\
+             XMASAMX.MM\n\
+             X.....XA.A\n\
+             S.S.S.S.SS\n\
+             .A.A.A.A.A\n\
+             ..M.M.M.MM\n\
+             .X.X.XMASX\n"
+        );
+    }
+
+    #[test]
+    fn test_render_heatmap() {
+        let board = Board::from_str("XMAS\nMM..\nA.A.\nS..S").unwrap();
+        let matches: Vec<Vec<(usize, usize)>> = board
+            .find_words(&["XMAS"])
+            .iter()
+            .map(|found| board.word_cells(found))
+            .collect();
+
+        assert_eq!(board.render_heatmap(&matches), "3111\n11..\n1.1.\n1..1\n");
+    }
+
+    #[test]
+    fn test_coverage_counts_wrapped_match_once() {
+        let board = Board::from_str("AB")
+            .unwrap()
+            .with_boundary(Boundary::WrapHorizontal);
+        let found = board.find_words(&["ABABA"]);
+        let matches: Vec<Vec<(usize, usize)>> =
+            found.iter().map(|found| board.word_cells(found)).collect();
+
+        // east and west from the A walk the same cells, so this is one match that visits
+        // both cells several times
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0], vec![(0, 0), (0, 1), (0, 0), (0, 1), (0, 0)]);
+        assert_eq!(board.coverage(&matches), vec![vec![1, 1]]);
+    }
+
+    #[test]
+    fn test_counts_a_small() {
+        assert_eq!(small_board().counts_a(), 18);
+    }
+
+    #[test]
+    fn test_find_words_locations() {
+        let board = Board::from_str("XMAS\n.A..\n..M.\nSAMX").unwrap();
+        let mut matches = board.find_words(&["XMAS", "SAMX"]);
+        matches.sort();
+
+        assert_eq!(
+            matches,
+            vec![
+                WordMatch {
+                    start: (0, 0),
+                    direction: Direction::East,
+                    word: "XMAS".into()
+                },
+                WordMatch {
+                    start: (0, 3),
+                    direction: Direction::West,
+                    word: "SAMX".into()
+                },
+                WordMatch {
+                    start: (3, 0),
+                    direction: Direction::East,
+                    word: "SAMX".into()
+                },
+                WordMatch {
+                    start: (3, 3),
+                    direction: Direction::West,
+                    word: "XMAS".into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_find_words_matches_brute_force() {
+        let board = small_board();
+        let mut words = vec!["XMAS", "MAS", "SAM", "AM", "A", "MM", "XMASAMX", "SAMXMAS"];
+        let generated: Vec<String> = ["M", "A", "S", "X"]
+            .iter()
+            .flat_map(|a| ["M", "A", "S", "X"].map(|b| format!("{a}{b}")))
+            .flat_map(|ab| ["M", "A", "S"].map(|c| format!("{ab}{c}")))
+            .collect();
+        words.extend(generated.iter().map(|word| word.as_str()));
+
+        let mut matches = board.find_words(&words);
+        matches.sort();
+
+        assert_eq!(matches, find_words_brute(&board, &words));
+    }
 }
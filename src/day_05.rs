@@ -1,8 +1,13 @@
 use anyhow::Result;
-use std::{cmp::Ordering, fs, num::ParseIntError, ops::Deref};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
+    fs,
+    num::ParseIntError,
+    ops::Deref,
+};
 
 #[derive(Debug, Clone)]
-struct Rules(Vec<(i32, i32)>);
+pub struct Rules(Vec<(i32, i32)>);
 
 impl Deref for Rules {
     type Target = Vec<(i32, i32)>;
@@ -12,11 +17,573 @@ impl Deref for Rules {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReorderError {
+    /// The rules between these pages go round in a circle, so no order satisfies them.
+    Cycle(Vec<i32>),
+    /// Nothing in the rules decides which of these pages comes first.
+    Ambiguous(Vec<i32>),
+}
+
+/// A rule `X|Y` broken by an update, with the positions of `X` and `Y` in it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Violation {
+    pub rule: (i32, i32),
+    pub left_index: usize,
+    pub right_index: usize,
+}
+
+/// Takes `page` out of position `from` and puts it back so it ends up at position `to`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Move {
+    pub page: i32,
+    pub from: usize,
+    pub to: usize,
+}
+
+/// Rules indexed by page, so looking one up does not mean scanning the whole list.
+#[derive(Debug, Clone, Default)]
+pub struct RuleIndex {
+    pairs: HashSet<(i32, i32)>,
+    successors: HashMap<i32, Vec<i32>>,
+}
+
+impl RuleIndex {
+    pub fn contains(&self, left: i32, right: i32) -> bool {
+        self.pairs.contains(&(left, right))
+    }
+
+    /// Pages that `page` must come before.
+    pub fn successors(&self, page: i32) -> &[i32] {
+        self.successors
+            .get(&page)
+            .map_or(&[], |pages| pages.as_slice())
+    }
+
+    /// An update is valid when no rule `X|Y` has `Y` printed before `X`. Each page only
+    /// looks up its own rules in a page -> position map, so this is linear in the update
+    /// plus the rules that mention its pages.
+    pub fn is_valid(&self, update: &[i32]) -> bool {
+        let positions: HashMap<i32, usize> = update
+            .iter()
+            .enumerate()
+            .map(|(index, page)| (*page, index))
+            .collect();
+
+        update.iter().enumerate().all(|(index, page)| {
+            self.successors(*page)
+                .iter()
+                .all(|next| positions.get(next).is_none_or(|position| *position > index))
+        })
+    }
+
+    /// Every rule the update breaks, ordered by where its `X` page sits in the update.
+    pub fn violations(&self, update: &[i32]) -> Vec<Violation> {
+        let positions: HashMap<i32, usize> = update
+            .iter()
+            .enumerate()
+            .map(|(index, page)| (*page, index))
+            .collect();
+
+        let mut violations: Vec<Violation> = update
+            .iter()
+            .enumerate()
+            .flat_map(|(left_index, page)| {
+                let positions = &positions;
+                self.successors(*page).iter().filter_map(move |next| {
+                    positions
+                        .get(next)
+                        .filter(|right_index| **right_index < left_index)
+                        .map(|right_index| Violation {
+                            rule: (*page, *next),
+                            left_index,
+                            right_index: *right_index,
+                        })
+                })
+            })
+            .collect();
+
+        violations.sort_by_key(|violation| (violation.left_index, violation.right_index));
+        violations
+    }
+
+    /// Pages reachable from `from` by following rules, never using the `skip` rule.
+    fn reachable(&self, from: i32, skip: Option<(i32, i32)>) -> HashSet<i32> {
+        let mut seen = HashSet::new();
+        let mut queue = VecDeque::from([from]);
+
+        while let Some(page) = queue.pop_front() {
+            for next in self.successors(page) {
+                if skip == Some((page, *next)) {
+                    continue;
+                }
+                if seen.insert(*next) {
+                    queue.push_back(*next);
+                }
+            }
+        }
+
+        seen
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RuleCheck {
+    /// Groups of pages whose rules lead from each one back to itself.
+    pub cycles: Vec<Vec<i32>>,
+    /// Rules already implied by a chain of other rules.
+    pub redundant: Vec<(i32, i32)>,
+    /// Pages printed in some update that no rule mentions.
+    pub unmentioned: Vec<i32>,
+}
+
+impl Rules {
+    /// Original check: every pair of pages must appear as a rule, scanning all rules for
+    /// each pair. It agrees with `RuleIndex::is_valid` whenever the rules cover every pair
+    /// of pages in the update, as they do in the puzzle input.
+    pub fn is_valid_brute(&self, update: &[i32]) -> bool {
+        generate_pairs(update)
+            .iter()
+            .all(|pair| self.iter().any(|rule| pair == rule))
+    }
+
+    /// Every page mentioned by a rule, sorted.
+    pub fn pages(&self) -> Vec<i32> {
+        let pages: BTreeSet<i32> = self
+            .iter()
+            .flat_map(|(left, right)| [*left, *right])
+            .collect();
+        pages.into_iter().collect()
+    }
+
+    /// Validates the rule section on its own: cycles across the whole rule graph, rules that
+    /// others already imply, and update pages that no rule talks about.
+    pub fn check(&self, updates: &PageUpdates) -> RuleCheck {
+        let index = self.index();
+        let pages = self.pages();
+        let reachable: HashMap<i32, HashSet<i32>> = pages
+            .iter()
+            .map(|page| (*page, index.reachable(*page, None)))
+            .collect();
+
+        let mut cycles: Vec<Vec<i32>> = Vec::new();
+        for page in pages.iter() {
+            if !reachable[page].contains(page) || cycles.iter().any(|c| c.contains(page)) {
+                continue;
+            }
+
+            let cycle = pages
+                .iter()
+                .copied()
+                .filter(|other| reachable[page].contains(other) && reachable[other].contains(page))
+                .collect();
+            cycles.push(cycle);
+        }
+
+        let mut seen = HashSet::new();
+        let redundant = self
+            .iter()
+            .copied()
+            .filter(|rule| seen.insert(*rule))
+            .filter(|(left, right)| {
+                index
+                    .reachable(*left, Some((*left, *right)))
+                    .contains(right)
+            })
+            .collect();
+
+        let mentioned: HashSet<i32> = pages.into_iter().collect();
+        let unmentioned: BTreeSet<i32> = updates
+            .0
+            .iter()
+            .flatten()
+            .copied()
+            .filter(|page| !mentioned.contains(page))
+            .collect();
+
+        RuleCheck {
+            cycles,
+            redundant,
+            unmentioned: unmentioned.into_iter().collect(),
+        }
+    }
+
+    /// The smallest rule set with the same consequences. Only defined when the rules have no
+    /// cycle, otherwise which rules to drop depends on the order we look at them in.
+    pub fn transitive_reduction(&self) -> Result<Rules, ReorderError> {
+        let check = self.check(&PageUpdates(Vec::new()));
+        if let Some(cycle) = check.cycles.into_iter().next() {
+            return Err(ReorderError::Cycle(cycle));
+        }
+
+        let mut seen = HashSet::new();
+        let reduced = self
+            .iter()
+            .copied()
+            .filter(|rule| seen.insert(*rule) && !check.redundant.contains(rule))
+            .collect();
+
+        Ok(Rules(reduced))
+    }
+
+    /// Fewest single-page moves that put `update` in rule order. The rules only have to
+    /// order the pages partially. A page can stay put as long as no page printed after it
+    /// belongs before it, following chains of rules through the update's own pages, so the
+    /// pages that stay are the largest set with no such pair between them. Every other page
+    /// is moved once, next to the pages it belongs after.
+    pub fn minimal_moves(&self, update: &[i32]) -> Result<Vec<Move>, ReorderError> {
+        let index = self.index();
+        let positions: HashMap<i32, usize> = update
+            .iter()
+            .enumerate()
+            .map(|(position, page)| (*page, position))
+            .collect();
+
+        // before[i][j]: a chain of rules between the update's pages puts update[i] ahead of
+        // update[j]
+        let before: Vec<Vec<bool>> = update
+            .iter()
+            .map(|page| {
+                let mut row = vec![false; update.len()];
+                let mut stack = vec![*page];
+                while let Some(current) = stack.pop() {
+                    for next in index.successors(current) {
+                        if let Some(position) = positions.get(next) {
+                            if !row[*position] {
+                                row[*position] = true;
+                                stack.push(*next);
+                            }
+                        }
+                    }
+                }
+                row
+            })
+            .collect();
+
+        let cycle: Vec<i32> = update
+            .iter()
+            .enumerate()
+            .filter(|(position, _)| before[*position][*position])
+            .map(|(_, page)| *page)
+            .collect();
+        if !cycle.is_empty() {
+            return Err(ReorderError::Cycle(cycle));
+        }
+
+        // inverted[i][j]: update[i] is printed before update[j] but belongs after it
+        let inverted: Vec<Vec<bool>> = (0..update.len())
+            .map(|i| (0..update.len()).map(|j| i < j && before[j][i]).collect())
+            .collect();
+        let kept = largest_antichain(&inverted);
+
+        // Target order: the rules, plus the kept pages staying in the order they are printed.
+        let mut successors: Vec<Vec<usize>> = before
+            .iter()
+            .map(|row| (0..row.len()).filter(|next| row[*next]).collect())
+            .collect();
+        for pair in kept.windows(2) {
+            successors[pair[0]].push(pair[1]);
+        }
+
+        let mut in_degree = vec![0; update.len()];
+        for next in successors.iter().flatten() {
+            in_degree[*next] += 1;
+        }
+
+        let mut ready: BTreeSet<usize> = (0..update.len())
+            .filter(|position| in_degree[*position] == 0)
+            .collect();
+        let mut ordered = Vec::with_capacity(update.len());
+        while let Some(position) = ready.pop_first() {
+            ordered.push(position);
+            for next in successors[position].iter() {
+                in_degree[*next] -= 1;
+                if in_degree[*next] == 0 {
+                    ready.insert(*next);
+                }
+            }
+        }
+
+        let mut rank = vec![0; update.len()];
+        for (target, position) in ordered.iter().enumerate() {
+            rank[*position] = target;
+        }
+
+        let mut placed = vec![false; update.len()];
+        for position in kept {
+            placed[position] = true;
+        }
+
+        let mut current: Vec<usize> = (0..update.len()).collect();
+        let mut moves = Vec::new();
+
+        for position in ordered {
+            if placed[position] {
+                continue;
+            }
+
+            let from = current.iter().position(|p| *p == position).unwrap();
+            current.remove(from);
+
+            let to = current
+                .iter()
+                .rposition(|p| placed[*p] && rank[*p] < rank[position])
+                .map_or(0, |index| index + 1);
+            current.insert(to, position);
+
+            placed[position] = true;
+            moves.push(Move {
+                page: update[position],
+                from,
+                to,
+            });
+        }
+
+        Ok(moves)
+    }
+
+    /// Layer of each page in the rule graph restricted to `pages`: pages with no rule before
+    /// them are rank 0, the rest sit one past their furthest predecessor. `None` when the
+    /// rules between those pages have a cycle.
+    fn ranks(&self, pages: &[i32]) -> Option<HashMap<i32, usize>> {
+        let included: HashSet<i32> = pages.iter().copied().collect();
+        let index = self.index();
+        let mut in_degree: HashMap<i32, usize> = pages.iter().map(|page| (*page, 0)).collect();
+
+        for (left, right) in index.pairs.iter() {
+            if included.contains(left) && included.contains(right) {
+                *in_degree.get_mut(right).unwrap() += 1;
+            }
+        }
+
+        let mut ranks: HashMap<i32, usize> = HashMap::new();
+        let mut ready: VecDeque<i32> = pages
+            .iter()
+            .copied()
+            .filter(|page| in_degree[page] == 0)
+            .collect();
+
+        while let Some(page) = ready.pop_front() {
+            let rank = *ranks.entry(page).or_insert(0);
+
+            for next in index
+                .successors(page)
+                .iter()
+                .filter(|p| included.contains(p))
+            {
+                let next_rank = ranks.entry(*next).or_insert(0);
+                *next_rank = (*next_rank).max(rank + 1);
+
+                let degree = in_degree.get_mut(next).unwrap();
+                *degree -= 1;
+                if *degree == 0 {
+                    ready.push_back(*next);
+                }
+            }
+        }
+
+        (ranks.len() == pages.len()).then_some(ranks)
+    }
+
+    /// Graphviz DOT graph of the rules. With an update, only its pages and the rules between
+    /// them are drawn, the rules it breaks in red, and pages are laid out by topological rank.
+    pub fn to_dot(&self, update: Option<&[i32]>) -> String {
+        let pages = match update {
+            Some(update) => update.to_vec(),
+            None => self.pages(),
+        };
+        let included: HashSet<i32> = pages.iter().copied().collect();
+        let violated: HashSet<(i32, i32)> = update
+            .map(|update| self.index().violations(update))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|violation| violation.rule)
+            .collect();
+
+        let mut dot = String::from("digraph rules {\n    rankdir=LR;\n");
+
+        for page in pages.iter() {
+            dot.push_str(&format!("    {page};\n"));
+        }
+
+        let mut seen = HashSet::new();
+        for rule in self.iter() {
+            let (left, right) = rule;
+            if !included.contains(left) || !included.contains(right) || !seen.insert(rule) {
+                continue;
+            }
+
+            if violated.contains(rule) {
+                dot.push_str(&format!("    {left} -> {right} [color=red];\n"));
+            } else {
+                dot.push_str(&format!("    {left} -> {right};\n"));
+            }
+        }
+
+        if let Some(ranks) = update.and_then(|_| self.ranks(&pages)) {
+            let deepest = ranks.values().copied().max().unwrap_or(0);
+
+            for rank in 0..=deepest {
+                let same: Vec<String> = pages
+                    .iter()
+                    .filter(|page| ranks[page] == rank)
+                    .map(|page| page.to_string())
+                    .collect();
+                dot.push_str(&format!("    {{ rank=same; {}; }}\n", same.join("; ")));
+            }
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+
+    pub fn index(&self) -> RuleIndex {
+        let mut index = RuleIndex::default();
+
+        for (left, right) in self.iter() {
+            if index.pairs.insert((*left, *right)) {
+                index.successors.entry(*left).or_default().push(*right);
+            }
+        }
+
+        index
+    }
+
+    /// Orders `update` by a topological sort of the rules whose pages both appear in it.
+    /// Fails when those rules contain a cycle or allow more than one order.
+    pub fn reorder(&self, update: &[i32]) -> Result<Vec<i32>, ReorderError> {
+        let pages: HashSet<i32> = update.iter().copied().collect();
+        let mut successors: HashMap<i32, Vec<i32>> = HashMap::new();
+        let mut in_degree: HashMap<i32, usize> = update.iter().map(|page| (*page, 0)).collect();
+
+        for (left, right) in self.iter() {
+            if pages.contains(left) && pages.contains(right) {
+                successors.entry(*left).or_default().push(*right);
+                *in_degree.get_mut(right).unwrap() += 1;
+            }
+        }
+
+        let mut ready: Vec<i32> = update
+            .iter()
+            .copied()
+            .filter(|page| in_degree[page] == 0)
+            .collect();
+        let mut ordered = Vec::with_capacity(update.len());
+
+        while !ready.is_empty() {
+            if ready.len() > 1 {
+                ready.sort_by_key(|page| update.iter().position(|p| p == page));
+                return Err(ReorderError::Ambiguous(ready));
+            }
+
+            let page = ready.pop().unwrap();
+            ordered.push(page);
+
+            for next in successors.get(&page).into_iter().flatten() {
+                let degree = in_degree.get_mut(next).unwrap();
+                *degree -= 1;
+                if *degree == 0 {
+                    ready.push(*next);
+                }
+            }
+        }
+
+        if ordered.len() < update.len() {
+            // Pages after a cycle get stuck too; only report the ones that lead back to
+            // themselves.
+            let on_cycle = |start: i32| {
+                let mut stack: Vec<i32> = successors.get(&start).cloned().unwrap_or_default();
+                let mut seen = HashSet::new();
+                while let Some(page) = stack.pop() {
+                    if page == start {
+                        return true;
+                    }
+                    if seen.insert(page) {
+                        stack.extend(successors.get(&page).into_iter().flatten());
+                    }
+                }
+                false
+            };
+
+            let cycle = update
+                .iter()
+                .copied()
+                .filter(|page| !ordered.contains(page) && on_cycle(*page))
+                .collect();
+            return Err(ReorderError::Cycle(cycle));
+        }
+
+        Ok(ordered)
+    }
+}
+
 #[derive(Debug, Clone)]
-struct PageUpdates(Vec<Vec<i32>>);
+pub struct PageUpdates(Vec<Vec<i32>>);
 
-fn load_data() -> Result<(Rules, PageUpdates)> {
-    let content = fs::read_to_string("./src/input/05.input")?;
+#[derive(Debug, Clone)]
+pub struct InferredRules {
+    pub rules: Rules,
+    /// Pairs of pages, smaller first, that never share an update, so nothing says which one
+    /// goes first.
+    pub unconstrained: Vec<(i32, i32)>,
+}
+
+impl PageUpdates {
+    /// `Rules::minimal_moves` for each update, in order.
+    pub fn minimal_moves(&self, rules: &Rules) -> Vec<Result<Vec<Move>, ReorderError>> {
+        self.0
+            .iter()
+            .map(|update| rules.minimal_moves(update))
+            .collect()
+    }
+
+    /// Reverse-engineers rules from updates known to be in the right order. Rules only
+    /// apply between pages of the same update, and the only way to order two neighbours in
+    /// an update is a rule between them, so the smallest rule set that orders every update
+    /// is exactly its neighbouring pairs. Fails when one update's pair is reversed in another.
+    pub fn infer_rules(&self) -> Result<InferredRules, ReorderError> {
+        let mut seen = HashSet::new();
+        let rules = Rules(
+            self.0
+                .iter()
+                .flat_map(|update| update.windows(2).map(|pair| (pair[0], pair[1])))
+                .filter(|pair| seen.insert(*pair))
+                .collect(),
+        );
+
+        let index = rules.index();
+        for update in self.0.iter() {
+            if let Some(violation) = index.violations(update).first() {
+                let (left, right) = violation.rule;
+                return Err(ReorderError::Cycle(vec![left, right]));
+            }
+        }
+
+        let together: HashSet<(i32, i32)> = self
+            .0
+            .iter()
+            .flat_map(|update| generate_pairs(update))
+            .map(|(left, right)| (left.min(right), left.max(right)))
+            .collect();
+
+        let pages: BTreeSet<i32> = self.0.iter().flatten().copied().collect();
+        let mut unconstrained = Vec::new();
+        for left in pages.iter() {
+            for right in pages.range(left + 1..) {
+                if !together.contains(&(*left, *right)) {
+                    unconstrained.push((*left, *right));
+                }
+            }
+        }
+
+        Ok(InferredRules {
+            rules,
+            unconstrained,
+        })
+    }
+}
+
+fn parse_input(content: &str) -> Result<(Rules, PageUpdates)> {
     let mut rules = Vec::new();
     let mut page_updates = Vec::new();
 
@@ -45,6 +612,72 @@ fn load_data() -> Result<(Rules, PageUpdates)> {
     Ok((Rules(rules), PageUpdates(page_updates)))
 }
 
+fn load_data() -> Result<(Rules, PageUpdates)> {
+    let content = fs::read_to_string("./src/input/05.input")?;
+    parse_input(&content)
+}
+
+/// Largest set of indices no two of which are related by `above`, a strict partial order
+/// given as `above[i][j]` when `i` is below `j`. By Dilworth's theorem it is as large as
+/// the fewest chains covering every index, which come from a maximum matching between a
+/// lower and an upper copy of each index; König's theorem reads the antichain off that
+/// matching.
+fn largest_antichain(above: &[Vec<bool>]) -> Vec<usize> {
+    fn augment(
+        lower: usize,
+        above: &[Vec<bool>],
+        matched_upper: &mut [Option<usize>],
+        seen: &mut [bool],
+    ) -> bool {
+        for (upper, related) in above[lower].iter().enumerate() {
+            if !related || seen[upper] {
+                continue;
+            }
+            seen[upper] = true;
+
+            if matched_upper[upper].is_none_or(|other| augment(other, above, matched_upper, seen)) {
+                matched_upper[upper] = Some(lower);
+                return true;
+            }
+        }
+        false
+    }
+
+    let count = above.len();
+    let mut matched_upper: Vec<Option<usize>> = vec![None; count];
+    let unmatched: Vec<usize> = (0..count)
+        .filter(|lower| !augment(*lower, above, &mut matched_upper, &mut vec![false; count]))
+        .collect();
+
+    // Everything reachable from an unmatched lower copy by alternating paths
+    let mut reached_lower = vec![false; count];
+    let mut reached_upper = vec![false; count];
+    for lower in unmatched.iter() {
+        reached_lower[*lower] = true;
+    }
+
+    let mut stack = unmatched;
+    while let Some(lower) = stack.pop() {
+        for (upper, related) in above[lower].iter().enumerate() {
+            if !related || reached_upper[upper] {
+                continue;
+            }
+            reached_upper[upper] = true;
+
+            if let Some(next) = matched_upper[upper] {
+                if !reached_lower[next] {
+                    reached_lower[next] = true;
+                    stack.push(next);
+                }
+            }
+        }
+    }
+
+    (0..count)
+        .filter(|index| reached_lower[*index] && !reached_upper[*index])
+        .collect()
+}
+
 fn generate_pairs(input: &[i32]) -> Vec<(i32, i32)> {
     let mut pairs = Vec::new();
 
@@ -57,55 +690,123 @@ fn generate_pairs(input: &[i32]) -> Vec<(i32, i32)> {
     pairs
 }
 
-pub fn part_a() {
-    let (rules, updates) = load_data().unwrap();
+fn sum_valid_mid_points(rules: &Rules, updates: PageUpdates) -> i32 {
+    let index = rules.index();
     let mut mid_points = 0;
 
     for update in updates.0.into_iter() {
-        let pairs = generate_pairs(&update);
-        let is_valid = pairs
-            .iter()
-            .all(|pair| rules.iter().any(|rule| pair == rule));
-
-        if is_valid {
+        if index.is_valid(&update) {
             let index = update.len() / 2;
             mid_points += update[index];
         }
     }
 
-    println!("Part a: {mid_points}");
+    mid_points
 }
 
-pub fn part_b() {
-    let (rules, updates) = load_data().unwrap();
+/// Sum of the middle pages of the invalid updates once reordered, plus the updates that
+/// could not be reordered and why. Those are left out of the sum.
+fn sum_reordered_mid_points(
+    rules: &Rules,
+    updates: PageUpdates,
+) -> (i32, Vec<(Vec<i32>, ReorderError)>) {
+    let index = rules.index();
     let mut invalid_list = Vec::new();
 
     for update in updates.0.into_iter() {
-        let pairs = generate_pairs(&update);
-        let is_valid = pairs
-            .iter()
-            .all(|pair| rules.iter().any(|rule| pair == rule));
-
-        if !is_valid {
+        if !index.is_valid(&update) {
             invalid_list.push(update);
         }
     }
 
     let mut mid_points = 0;
-    for mut invalid in invalid_list {
-        invalid.sort_by(|left, right| {
-            let rule = rules.0.iter().any(|(l, r)| l == left && r == right);
-            if rule {
-                Ordering::Less
-            } else {
-                Ordering::Equal
+    let mut failures = Vec::new();
+    for invalid in invalid_list {
+        match rules.reorder(&invalid) {
+            Ok(ordered) => {
+                let index = ordered.len() / 2;
+                mid_points += ordered[index];
             }
-        });
+            Err(error) => failures.push((invalid, error)),
+        }
+    }
+
+    (mid_points, failures)
+}
+
+pub fn explain() {
+    let (rules, updates) = load_data().unwrap();
+    let index = rules.index();
 
-        let index = invalid.len() / 2;
-        mid_points += invalid[index];
+    for (line, update) in updates.0.iter().enumerate() {
+        let violations = index.violations(update);
+        if violations.is_empty() {
+            continue;
+        }
+
+        println!(
+            "Update {} {:?}: {} violations",
+            line + 1,
+            update,
+            violations.len()
+        );
+        for violation in violations {
+            let (left, right) = violation.rule;
+            println!(
+                "  {left}|{right}: {left} at {} but {right} at {}",
+                violation.left_index, violation.right_index
+            );
+        }
+    }
+}
+
+pub fn check_rules() {
+    let (rules, updates) = load_data().unwrap();
+    let check = rules.check(&updates);
+
+    for cycle in check.cycles.iter() {
+        println!("Cycle between {} pages: {:?}", cycle.len(), cycle);
+    }
+    println!(
+        "Redundant rules: {} of {}",
+        check.redundant.len(),
+        rules.len()
+    );
+    println!("Pages without rules: {:?}", check.unmentioned);
+
+    match rules.transitive_reduction() {
+        Ok(reduced) => {
+            for (left, right) in reduced.iter() {
+                println!("{left}|{right}");
+            }
+        }
+        Err(error) => println!("No transitive reduction: {:?}", error),
     }
+}
 
+/// Writes the rules as a DOT graph to `path`, optionally restricted to the update on
+/// `line` (counting from 1). Render it with e.g. `dot -Tsvg rules.dot -o rules.svg`.
+pub fn export_dot(line: Option<usize>, path: &str) {
+    let (rules, updates) = load_data().unwrap();
+    let update = line.map(|line| updates.0[line - 1].as_slice());
+
+    fs::write(path, rules.to_dot(update)).unwrap();
+}
+
+pub fn part_a() {
+    let (rules, updates) = load_data().unwrap();
+    let mid_points = sum_valid_mid_points(&rules, updates);
+
+    println!("Part a: {mid_points}");
+}
+
+pub fn part_b() {
+    let (rules, updates) = load_data().unwrap();
+    let (mid_points, failures) = sum_reordered_mid_points(&rules, updates);
+
+    for (update, error) in failures.iter() {
+        println!("Can not reorder {:?}: {:?}", update, error);
+    }
     println!("Part b: {mid_points}");
 }
 
@@ -114,6 +815,416 @@ mod test {
     use std::cmp::Ordering;
 
     use super::*;
+    use crate::test_util::Lcg;
+
+    fn small_input() -> (Rules, PageUpdates) {
+        let content = fs::read_to_string("./src/input/05_sm.input").unwrap();
+        parse_input(&content).unwrap()
+    }
+
+    #[test]
+    fn test_is_valid_matches_brute_on_inputs() {
+        for (rules, updates) in [small_input(), load_data().unwrap()] {
+            let index = rules.index();
+
+            for update in updates.0.iter() {
+                assert_eq!(
+                    index.is_valid(update),
+                    rules.is_valid_brute(update),
+                    "{:?}",
+                    update
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_is_valid_matches_brute_on_random_orders() {
+        let mut rng = Lcg(5);
+
+        for _ in 0..200 {
+            let mut order: Vec<i32> = (10..40).collect();
+            rng.shuffle(&mut order);
+            let rules = Rules(generate_pairs(&order));
+            let index = rules.index();
+
+            for _ in 0..20 {
+                let len = 1 + rng.below(9);
+                let mut update = order.clone();
+                rng.shuffle(&mut update);
+                update.truncate(len);
+
+                assert_eq!(index.is_valid(&update), rules.is_valid_brute(&update));
+            }
+        }
+    }
+
+    #[test]
+    fn test_violations_small() {
+        let (rules, updates) = small_input();
+        let index = rules.index();
+
+        let counts: Vec<usize> = updates
+            .0
+            .iter()
+            .map(|update| index.violations(update).len())
+            .collect();
+        assert_eq!(counts, vec![0, 0, 0, 1, 1, 4]);
+
+        assert_eq!(
+            index.violations(&[75, 97, 47, 61, 53]),
+            vec![Violation {
+                rule: (97, 75),
+                left_index: 1,
+                right_index: 0
+            }]
+        );
+        assert_eq!(
+            index.violations(&[61, 13, 29]),
+            vec![Violation {
+                rule: (29, 13),
+                left_index: 2,
+                right_index: 1
+            }]
+        );
+    }
+
+    #[test]
+    fn test_violations_agree_with_is_valid() {
+        let (rules, updates) = load_data().unwrap();
+        let index = rules.index();
+
+        for update in updates.0.iter() {
+            assert_eq!(index.violations(update).is_empty(), index.is_valid(update));
+        }
+    }
+
+    #[test]
+    fn test_check_small() {
+        let (rules, updates) = small_input();
+        let check = rules.check(&updates);
+
+        assert!(check.cycles.is_empty());
+        assert!(check.unmentioned.is_empty());
+        // the 21 rules order all 7 pages, only the 6 between neighbours are needed
+        assert_eq!(check.redundant.len(), 15);
+        let mut reduced = rules.transitive_reduction().unwrap().0;
+        reduced.sort();
+        assert_eq!(
+            reduced,
+            vec![(29, 13), (47, 61), (53, 29), (61, 53), (75, 47), (97, 75)]
+        );
+    }
+
+    #[test]
+    fn test_check_cycles_and_unmentioned() {
+        let rules = Rules(vec![(1, 2), (2, 3), (3, 1), (3, 4), (1, 4), (5, 5)]);
+        let updates = PageUpdates(vec![vec![1, 2, 9], vec![7, 9]]);
+        let check = rules.check(&updates);
+
+        assert_eq!(check.cycles, vec![vec![1, 2, 3], vec![5]]);
+        assert_eq!(check.unmentioned, vec![7, 9]);
+        assert!(check.redundant.contains(&(1, 4)));
+        assert_eq!(
+            rules.transitive_reduction().unwrap_err(),
+            ReorderError::Cycle(vec![1, 2, 3])
+        );
+    }
+
+    #[test]
+    fn test_transitive_reduction_keeps_consequences() {
+        let rules = Rules(vec![(1, 2), (2, 3), (1, 3), (3, 4), (1, 4), (2, 4), (2, 3)]);
+        let reduced = rules.transitive_reduction().unwrap();
+
+        assert_eq!(reduced.0, vec![(1, 2), (2, 3), (3, 4)]);
+        for page in rules.pages() {
+            assert_eq!(
+                reduced.index().reachable(page, None),
+                rules.index().reachable(page, None)
+            );
+        }
+    }
+
+    #[test]
+    fn test_infer_rules_small() {
+        let (rules, updates) = small_input();
+        let index = rules.index();
+        let valid = PageUpdates(
+            updates
+                .0
+                .into_iter()
+                .filter(|update| index.is_valid(update))
+                .collect(),
+        );
+
+        let inferred = valid.infer_rules().unwrap();
+        let mut inferred_rules = inferred.rules.0.clone();
+        inferred_rules.sort();
+
+        // 75|29 is needed, nothing else sits between them in the last update
+        assert_eq!(
+            inferred_rules,
+            vec![
+                (29, 13),
+                (47, 61),
+                (53, 29),
+                (61, 53),
+                (75, 29),
+                (75, 47),
+                (97, 61)
+            ]
+        );
+        assert_eq!(inferred.unconstrained, vec![(13, 47), (47, 97), (75, 97)]);
+    }
+
+    #[test]
+    fn test_infer_rules_round_trip() {
+        let (rules, updates) = load_data().unwrap();
+        let index = rules.index();
+        let valid = PageUpdates(
+            updates
+                .0
+                .into_iter()
+                .filter(|update| index.is_valid(update))
+                .collect(),
+        );
+
+        let inferred = valid.infer_rules().unwrap().rules;
+        let inferred_index = inferred.index();
+        for update in valid.0.iter() {
+            assert!(inferred_index.is_valid(update), "{:?}", update);
+
+            // the inferred rules alone put a shuffled update back in order
+            let mut shuffled = update.clone();
+            shuffled.reverse();
+            assert_eq!(inferred.reorder(&shuffled).as_ref(), Ok(update));
+        }
+    }
+
+    #[test]
+    fn test_infer_rules_contradiction() {
+        let updates = PageUpdates(vec![vec![1, 2, 3], vec![3, 1]]);
+
+        assert_eq!(
+            updates.infer_rules().unwrap_err(),
+            ReorderError::Cycle(vec![3, 1])
+        );
+    }
+
+    fn apply_moves(update: &[i32], moves: &[Move]) -> Vec<i32> {
+        let mut current = update.to_vec();
+
+        for step in moves {
+            assert_eq!(current[step.from], step.page);
+            current.remove(step.from);
+            current.insert(step.to, step.page);
+        }
+
+        current
+    }
+
+    /// Breadth-first search over single-page moves until the update is valid.
+    fn minimal_moves_brute(index: &RuleIndex, update: &[i32]) -> usize {
+        let mut seen = HashSet::from([update.to_vec()]);
+        let mut queue = VecDeque::from([(update.to_vec(), 0)]);
+
+        while let Some((current, moves)) = queue.pop_front() {
+            if index.is_valid(&current) {
+                return moves;
+            }
+
+            for from in 0..current.len() {
+                for to in 0..current.len() {
+                    let mut next = current.clone();
+                    let page = next.remove(from);
+                    next.insert(to, page);
+
+                    if seen.insert(next.clone()) {
+                        queue.push_back((next, moves + 1));
+                    }
+                }
+            }
+        }
+
+        unreachable!("every update can be sorted")
+    }
+
+    #[test]
+    fn test_minimal_moves_small() {
+        let (rules, updates) = small_input();
+        let moves = updates.minimal_moves(&rules);
+
+        let counts: Vec<usize> = moves
+            .iter()
+            .map(|moves| moves.as_ref().unwrap().len())
+            .collect();
+        assert_eq!(counts, vec![0, 0, 0, 1, 1, 2]);
+
+        assert_eq!(
+            moves[3],
+            Ok(vec![Move {
+                page: 75,
+                from: 0,
+                to: 1
+            }])
+        );
+        assert_eq!(
+            apply_moves(&updates.0[5], moves[5].as_ref().unwrap()),
+            vec![97, 75, 47, 29, 13]
+        );
+    }
+
+    #[test]
+    fn test_minimal_moves_matches_brute_force() {
+        let mut rng = Lcg(43);
+
+        for _ in 0..300 {
+            let mut order: Vec<i32> = (10..30).collect();
+            rng.shuffle(&mut order);
+            let rules = Rules(generate_pairs(&order));
+            let index = rules.index();
+
+            let mut update = order.clone();
+            rng.shuffle(&mut update);
+            update.truncate(1 + rng.below(6));
+
+            let moves = rules.minimal_moves(&update).unwrap();
+            assert_eq!(
+                moves.len(),
+                minimal_moves_brute(&index, &update),
+                "{:?}",
+                update
+            );
+            assert!(
+                index.is_valid(&apply_moves(&update, &moves)),
+                "{:?}",
+                update
+            );
+        }
+    }
+
+    #[test]
+    fn test_minimal_moves_partial_order() {
+        let rules = Rules(vec![(1, 2), (1, 3)]);
+        assert_eq!(
+            rules.minimal_moves(&[3, 2, 1]),
+            Ok(vec![Move {
+                page: 1,
+                from: 2,
+                to: 0
+            }])
+        );
+        assert_eq!(rules.minimal_moves(&[1, 3, 2]), Ok(vec![]));
+
+        // 3|2 only through 4, which sits between them and has to move anyway
+        let rules = Rules(vec![(3, 4), (4, 2)]);
+        let moves = rules.minimal_moves(&[2, 4, 3]).unwrap();
+        assert_eq!(moves.len(), 2);
+        assert!(rules.index().is_valid(&apply_moves(&[2, 4, 3], &moves)));
+
+        let rules = Rules(vec![(1, 2), (2, 1)]);
+        assert_eq!(
+            rules.minimal_moves(&[1, 2, 3]),
+            Err(ReorderError::Cycle(vec![1, 2]))
+        );
+    }
+
+    #[test]
+    fn test_minimal_moves_partial_orders_match_brute_force() {
+        let mut rng = Lcg(4343);
+
+        for _ in 0..300 {
+            let mut order: Vec<i32> = (10..20).collect();
+            rng.shuffle(&mut order);
+            let rules = Rules(
+                generate_pairs(&order)
+                    .into_iter()
+                    .filter(|_| rng.below(3) == 0)
+                    .collect(),
+            );
+            let index = rules.index();
+
+            let mut update = order.clone();
+            rng.shuffle(&mut update);
+            update.truncate(1 + rng.below(6));
+
+            let moves = rules.minimal_moves(&update).unwrap();
+            assert_eq!(
+                moves.len(),
+                minimal_moves_brute(&index, &update),
+                "{:?} {:?}",
+                rules,
+                update
+            );
+            assert!(
+                index.is_valid(&apply_moves(&update, &moves)),
+                "{:?} {:?}",
+                rules,
+                update
+            );
+        }
+    }
+
+    #[test]
+    fn test_largest_antichain() {
+        let chain = |n: usize| -> Vec<Vec<bool>> {
+            (0..n).map(|i| (0..n).map(|j| i < j).collect()).collect()
+        };
+        assert_eq!(largest_antichain(&[]), Vec::<usize>::new());
+        assert_eq!(largest_antichain(&chain(3)).len(), 1);
+        assert_eq!(largest_antichain(&vec![vec![false; 3]; 3]), vec![0, 1, 2]);
+
+        // 0 < 2, 1 < 2, 1 < 3: {0, 1} and {0, 3} and {2, 3} are the widest
+        let mut above = vec![vec![false; 4]; 4];
+        above[0][2] = true;
+        above[1][2] = true;
+        above[1][3] = true;
+        assert_eq!(largest_antichain(&above).len(), 2);
+    }
+
+    #[test]
+    fn test_to_dot_update() {
+        let rules = Rules(vec![(1, 2), (2, 3), (1, 3), (3, 4), (5, 1)]);
+
+        assert_eq!(
+            rules.to_dot(Some(&[2, 1, 3])),
+            "digraph rules {
+    rankdir=LR;
+    2;
+    1;
+    3;
+    1 -> 2 [color=red];
+    2 -> 3;
+    1 -> 3;
+    { rank=same; 1; }
+    { rank=same; 2; }
+    { rank=same; 3; }
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_to_dot_full_graph() {
+        let (rules, _updates) = small_input();
+        let dot = rules.to_dot(None);
+
+        assert!(dot.starts_with("digraph rules {\n"));
+        assert_eq!(dot.matches(" -> ").count(), 21);
+        assert!(!dot.contains("color=red"));
+        assert!(!dot.contains("rank=same"));
+    }
+
+    #[test]
+    fn test_rule_index() {
+        let (rules, _updates) = small_input();
+        let index = rules.index();
+
+        assert!(index.contains(47, 53));
+        assert!(!index.contains(53, 47));
+        assert_eq!(index.successors(29), &[13]);
+        assert!(index.successors(13).is_empty());
+    }
 
     #[test]
     fn test_part_b() {
@@ -133,4 +1244,64 @@ mod test {
 
         println!("After: {:?}", invalid);
     }
+
+    #[test]
+    fn test_small_example() {
+        let (rules, updates) = small_input();
+
+        assert_eq!(sum_valid_mid_points(&rules, updates.clone()), 143);
+        assert_eq!(sum_reordered_mid_points(&rules, updates), (123, Vec::new()));
+    }
+
+    #[test]
+    fn test_reorder_small() {
+        let (rules, _updates) = small_input();
+
+        assert_eq!(
+            rules.reorder(&[75, 97, 47, 61, 53]),
+            Ok(vec![97, 75, 47, 61, 53])
+        );
+        assert_eq!(rules.reorder(&[61, 13, 29]), Ok(vec![61, 29, 13]));
+        assert_eq!(
+            rules.reorder(&[97, 13, 75, 29, 47]),
+            Ok(vec![97, 75, 47, 29, 13])
+        );
+        assert_eq!(rules.reorder(&[75, 29, 13]), Ok(vec![75, 29, 13]));
+    }
+
+    #[test]
+    fn test_reorder_reports_cycles_and_ambiguity() {
+        let rules = Rules(vec![(1, 2), (2, 3), (3, 1), (3, 4)]);
+        assert_eq!(
+            rules.reorder(&[4, 3, 2, 1]),
+            Err(ReorderError::Cycle(vec![3, 2, 1]))
+        );
+
+        // 5 sits between two cycles without being on either
+        let rules = Rules(vec![(1, 2), (2, 1), (2, 5), (5, 3), (3, 4), (4, 3)]);
+        assert_eq!(
+            rules.reorder(&[1, 2, 3, 4, 5]),
+            Err(ReorderError::Cycle(vec![1, 2, 3, 4]))
+        );
+
+        let updates = PageUpdates(vec![vec![4, 3, 2, 1], vec![2, 1]]);
+        let rules = Rules(vec![(1, 2), (2, 3), (3, 1), (3, 4)]);
+        assert_eq!(
+            sum_reordered_mid_points(&rules, updates),
+            (
+                2,
+                vec![(vec![4, 3, 2, 1], ReorderError::Cycle(vec![3, 2, 1]))]
+            )
+        );
+
+        let rules = Rules(vec![(1, 2), (1, 3)]);
+        assert_eq!(
+            rules.reorder(&[3, 2, 1]),
+            Err(ReorderError::Ambiguous(vec![3, 2]))
+        );
+
+        // rules about pages outside the update do not matter
+        let rules = Rules(vec![(1, 2), (2, 3), (3, 1)]);
+        assert_eq!(rules.reorder(&[2, 1]), Ok(vec![1, 2]));
+    }
 }
@@ -1,5 +1,9 @@
-use anyhow::Result;
-use std::{collections::HashMap, fs};
+use anyhow::{bail, Result};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, fs, thread,
+    time::Instant,
+};
 
 #[derive(Debug, Clone, PartialEq)]
 enum Piece {
@@ -7,30 +11,212 @@ enum Piece {
     Obstacle,
     Visited,
     OutOfBounds,
+    Custom { behaviour: Behaviour, symbol: char },
 }
 
-#[derive(Debug, Clone)]
-enum Direction {
+impl Piece {
+    /// Whether the guard turns in front of this piece instead of walking onto it.
+    fn blocks(&self) -> bool {
+        matches!(
+            self,
+            Piece::Obstacle
+                | Piece::Custom {
+                    behaviour: Behaviour::Blocks,
+                    ..
+                }
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
     Up,
     Down,
     Left,
     Right,
 }
 
+impl Direction {
+    /// Cell one step away. Stepping off the top or left edge wraps to `usize::MAX`, which
+    /// `Board::piece_at` reports as out of bounds.
+    pub fn step(&self, (row, col): (usize, usize)) -> (usize, usize) {
+        match self {
+            Direction::Up => (row.wrapping_sub(1), col),
+            Direction::Down => (row + 1, col),
+            Direction::Left => (row, col.wrapping_sub(1)),
+            Direction::Right => (row, col + 1),
+        }
+    }
+
+    /// Bit of this direction in a route cell mark.
+    pub fn bit(&self) -> u8 {
+        match self {
+            Direction::Up => 1,
+            Direction::Down => 2,
+            Direction::Left => 4,
+            Direction::Right => 8,
+        }
+    }
+
+    pub fn symbol(&self) -> char {
+        match self {
+            Direction::Up => '^',
+            Direction::Down => 'v',
+            Direction::Left => '<',
+            Direction::Right => '>',
+        }
+    }
+
+    pub fn opposite(&self) -> Direction {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Turn {
+    Right,
+    Left,
+    Reverse,
+}
+
+impl Turn {
+    pub fn apply(&self, direction: Direction) -> Direction {
+        match (self, direction) {
+            (Turn::Reverse, direction) => direction.opposite(),
+            (Turn::Right, Direction::Up) | (Turn::Left, Direction::Down) => Direction::Right,
+            (Turn::Right, Direction::Right) | (Turn::Left, Direction::Left) => Direction::Down,
+            (Turn::Right, Direction::Down) | (Turn::Left, Direction::Up) => Direction::Left,
+            (Turn::Right, Direction::Left) | (Turn::Left, Direction::Right) => Direction::Up,
+        }
+    }
+}
+
+/// What a guard does when it runs into an obstacle: always the same turn, or the turns of a
+/// sequence in order, starting over once it runs out. An empty sequence turns right.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TurnRule {
+    Always(Turn),
+    Sequence(Vec<Turn>),
+}
+
+impl Default for TurnRule {
+    fn default() -> Self {
+        TurnRule::Always(Turn::Right)
+    }
+}
+
+impl TurnRule {
+    pub fn turn(&self, turns_taken: usize) -> Turn {
+        match self {
+            TurnRule::Sequence(turns) if !turns.is_empty() => turns[turns_taken % turns.len()],
+            TurnRule::Sequence(_) => Turn::Right,
+            TurnRule::Always(turn) => *turn,
+        }
+    }
+
+    /// Where in its sequence the rule is. Two guard states only repeat if this matches too.
+    pub fn phase(&self, turns_taken: usize) -> usize {
+        match self {
+            TurnRule::Sequence(turns) if !turns.is_empty() => turns_taken % turns.len(),
+            _ => 0,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 struct Guard {
     position: (usize, usize),
     direction: Direction,
+    rule: TurnRule,
+    turns_taken: usize,
 }
 
 impl Guard {
+    pub fn new(position: (usize, usize), direction: Direction) -> Guard {
+        Guard {
+            position,
+            direction,
+            rule: TurnRule::default(),
+            turns_taken: 0,
+        }
+    }
+
+    pub fn with_rule(mut self, rule: TurnRule) -> Guard {
+        self.rule = rule;
+        self
+    }
+
+    /// Turns according to the guard's rule, right unless told otherwise.
     pub fn rotate_direction(&mut self) {
-        self.direction = match self.direction {
-            Direction::Up => Direction::Right,
-            Direction::Right => Direction::Down,
-            Direction::Down => Direction::Left,
-            Direction::Left => Direction::Up,
+        self.direction = self.rule.turn(self.turns_taken).apply(self.direction);
+        self.turns_taken += 1;
+    }
+
+    /// One step of the patrol: turn if an obstacle (or `extra`) is in the way, otherwise
+    /// move forward. Returns `false`, without moving, once the next cell is off the board.
+    pub fn advance(&mut self, board: &Board, extra: Option<(usize, usize)>) -> bool {
+        let next = self.direction.step(self.position);
+        let piece = if Some(next) == extra {
+            Piece::Obstacle
+        } else {
+            board.piece_at(next.0, next.1)
         };
+
+        match piece {
+            Piece::OutOfBounds => return false,
+            piece if piece.blocks() => self.rotate_direction(),
+            _ => self.position = next,
+        }
+        true
+    }
+
+    /// Everything that decides where the guard goes next; seeing it twice means a loop.
+    pub fn state(&self) -> ((usize, usize), Direction, usize) {
+        (
+            self.position,
+            self.direction,
+            self.rule.phase(self.turns_taken),
+        )
+    }
+}
+
+/// How a guard's patrol ended, with the tick it happened on. A looping guard keeps walking,
+/// so a later collision still replaces its `Loop`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Exit {
+        step: usize,
+    },
+    Loop {
+        step: usize,
+    },
+    Collision {
+        step: usize,
+        position: (usize, usize),
+        with: Vec<usize>,
+    },
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Outcome::Exit { step } => write!(f, "left the board after {step} steps"),
+            Outcome::Loop { step } => write!(f, "stuck in a loop after {step} steps"),
+            Outcome::Collision {
+                step,
+                position,
+                with,
+            } => write!(
+                f,
+                "collided at {:?} after {step} steps with guard(s) {:?}",
+                position, with
+            ),
+        }
     }
 }
 
@@ -42,6 +228,7 @@ struct Board {
 }
 
 impl Board {
+    #[allow(dead_code)]
     pub fn print(&self) {
         for row in 0..self.rows {
             for col in 0..self.cols {
@@ -51,6 +238,7 @@ impl Board {
                     Piece::Obstacle => print!("#"),
                     Piece::Visited => print!("X"),
                     Piece::OutOfBounds => print!("O"),
+                    Piece::Custom { symbol, .. } => print!("{symbol}"),
                 }
             }
             println!();
@@ -77,39 +265,442 @@ impl Board {
     }
 }
 
+/// Positions of blocking pieces per row and per column, kept sorted so the guard can jump straight to
+/// the cell in front of the next obstacle instead of walking there one step at a time.
+#[derive(Debug, Clone)]
+struct JumpTable {
+    by_row: Vec<Vec<usize>>,
+    by_col: Vec<Vec<usize>>,
+}
+
+impl JumpTable {
+    pub fn new(board: &Board) -> JumpTable {
+        let mut by_row = vec![Vec::new(); board.rows];
+        let mut by_col = vec![Vec::new(); board.cols];
+
+        for (&(row, col), piece) in board.data.iter() {
+            if piece.blocks() {
+                by_row[row].push(col);
+                by_col[col].push(row);
+            }
+        }
+        by_row.iter_mut().for_each(|line| line.sort_unstable());
+        by_col.iter_mut().for_each(|line| line.sort_unstable());
+
+        JumpTable { by_row, by_col }
+    }
+
+    /// Cell where the guard stops in front of the next obstacle, or `None` if it walks off
+    /// the board. `extra` is a temporary obstacle checked alongside the index.
+    pub fn next_stop(
+        &self,
+        (row, col): (usize, usize),
+        direction: Direction,
+        extra: Option<(usize, usize)>,
+    ) -> Option<(usize, usize)> {
+        let obstacle = match direction {
+            Direction::Up => {
+                let line = &self.by_col[col];
+                let index = line.partition_point(|&r| r < row);
+                index.checked_sub(1).map(|index| (line[index], col))
+            }
+            Direction::Down => {
+                let line = &self.by_col[col];
+                let index = line.partition_point(|&r| r <= row);
+                line.get(index).map(|&r| (r, col))
+            }
+            Direction::Left => {
+                let line = &self.by_row[row];
+                let index = line.partition_point(|&c| c < col);
+                index.checked_sub(1).map(|index| (row, line[index]))
+            }
+            Direction::Right => {
+                let line = &self.by_row[row];
+                let index = line.partition_point(|&c| c <= col);
+                line.get(index).map(|&c| (row, c))
+            }
+        };
+
+        let ahead = |(r, c): (usize, usize)| match direction {
+            Direction::Up => c == col && r < row,
+            Direction::Down => c == col && r > row,
+            Direction::Left => r == row && c < col,
+            Direction::Right => r == row && c > col,
+        };
+        let distance = |(r, c): (usize, usize)| row.abs_diff(r) + col.abs_diff(c);
+
+        let obstacle = match (obstacle, extra.filter(|&cell| ahead(cell))) {
+            (Some(indexed), Some(extra)) if distance(extra) < distance(indexed) => Some(extra),
+            (indexed, extra) => indexed.or(extra),
+        };
+
+        obstacle.map(|cell| direction.opposite().step(cell))
+    }
+
+    /// Same answer as walking the guard one cell at a time, but only the states right after
+    /// each turn are recorded.
+    pub fn is_loop(&self, guard: &Guard, extra: Option<(usize, usize)>) -> bool {
+        let mut guard = guard.clone();
+        let mut seen = HashSet::new();
+
+        while let Some(stop) = self.next_stop(guard.position, guard.direction, extra) {
+            guard.position = stop;
+            guard.rotate_direction();
+            if !seen.insert(guard.state()) {
+                return true;
+            }
+        }
+
+        false
+    }
+}
+
+const VERTICAL: u8 = 1 | 2;
+const HORIZONTAL: u8 = 4 | 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct RouteStep {
+    position: (usize, usize),
+    direction: Direction,
+    step: usize,
+}
+
+/// Everything the guard did, in order: one entry per move or turn, so a corner shows up
+/// twice with both directions. `marks` holds the directions seen on each cell as a bitset of
+/// [`Direction::bit`].
+#[derive(Debug, Clone, Default)]
+struct Route {
+    steps: Vec<RouteStep>,
+    marks: HashMap<(usize, usize), u8>,
+    obstacle: Option<(usize, usize)>,
+    looped: bool,
+}
+
+impl Route {
+    /// Simulates the guard, with `extra` as an added obstacle, until it leaves the board or
+    /// repeats a state.
+    pub fn record(board: &Board, guard: &Guard, extra: Option<(usize, usize)>) -> Route {
+        let mut route = Route {
+            obstacle: extra,
+            ..Route::default()
+        };
+        let mut guard = guard.clone();
+        let mut seen = HashSet::new();
+
+        loop {
+            if !seen.insert(guard.state()) {
+                route.looped = true;
+                return route;
+            }
+            route.push(guard.position, guard.direction);
+
+            if !guard.advance(board, extra) {
+                return route;
+            }
+        }
+    }
+
+    fn push(&mut self, position: (usize, usize), direction: Direction) {
+        self.steps.push(RouteStep {
+            position,
+            direction,
+            step: self.steps.len(),
+        });
+        *self.marks.entry(position).or_default() |= direction.bit();
+    }
+
+    /// The route cut down to its first `count` steps.
+    pub fn first(&self, count: usize) -> Route {
+        let mut route = Route {
+            obstacle: self.obstacle,
+            looped: self.looped && count >= self.steps.len(),
+            ..Route::default()
+        };
+        for step in self.steps.iter().take(count) {
+            route.push(step.position, step.direction);
+        }
+        route
+    }
+
+    /// Draws the board like the puzzle illustrations: `|` and `-` for vertical and horizontal
+    /// travel, `+` where both cross, the starting guard and `O` for the added obstacle.
+    pub fn render(&self, board: &Board) -> String {
+        let mut output = String::new();
+
+        for row in 0..board.rows {
+            for col in 0..board.cols {
+                let mark = self.marks.get(&(row, col)).copied().unwrap_or(0);
+                let symbol = match self.steps.first() {
+                    Some(start) if start.position == (row, col) => start.direction.symbol(),
+                    _ if self.obstacle == Some((row, col)) => 'O',
+                    _ if mark & VERTICAL != 0 && mark & HORIZONTAL != 0 => '+',
+                    _ if mark & VERTICAL != 0 => '|',
+                    _ if mark & HORIZONTAL != 0 => '-',
+                    _ => match board.piece_at(row, col) {
+                        Piece::Obstacle => '#',
+                        Piece::Custom { symbol, .. } => symbol,
+                        _ => '.',
+                    },
+                };
+                output.push(symbol);
+            }
+            output.push('\n');
+        }
+
+        output
+    }
+
+    /// The rendered board followed by the steps as `step,row,col,direction` lines.
+    pub fn export(&self, board: &Board) -> String {
+        let mut output = self.render(board);
+        output.push_str("\nstep,row,col,direction\n");
+        for step in self.steps.iter() {
+            output.push_str(&format!(
+                "{},{},{},{}\n",
+                step.step,
+                step.position.0,
+                step.position.1,
+                step.direction.symbol()
+            ));
+        }
+        output
+    }
+}
+
+/// How the guard treats a [`Tile::Custom`] cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Behaviour {
+    /// The guard turns in front of it, like an obstacle.
+    Blocks,
+    /// The guard walks over it, like an empty cell.
+    Passable,
+}
+
+/// What a board character stands for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tile {
+    Empty,
+    Obstacle,
+    Guard(Direction),
+    /// A tile of its own that blocks or lets the guard through, drawn as `symbol` when the
+    /// board or a route is printed.
+    Custom {
+        behaviour: Behaviour,
+        symbol: char,
+    },
+}
+
+/// The characters a board may contain and the [`Tile`] each one stands for. Anything not
+/// registered is rejected by the parser.
+#[derive(Debug, Clone, Default)]
+pub struct TileSet {
+    tiles: HashMap<char, Tile>,
+}
+
+impl TileSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `.`, `#` and a guard facing `^`, `v`, `<` or `>`, as in the puzzle input.
+    pub fn standard() -> Self {
+        Self::new()
+            .register('.', Tile::Empty)
+            .register('#', Tile::Obstacle)
+            .register('^', Tile::Guard(Direction::Up))
+            .register('v', Tile::Guard(Direction::Down))
+            .register('<', Tile::Guard(Direction::Left))
+            .register('>', Tile::Guard(Direction::Right))
+    }
+
+    /// Reads `symbol` as `tile`, replacing whatever it stood for before.
+    pub fn register(mut self, symbol: char, tile: Tile) -> Self {
+        self.tiles.insert(symbol, tile);
+        self
+    }
+
+    pub fn tile(&self, symbol: char) -> Option<Tile> {
+        self.tiles.get(&symbol).copied()
+    }
+}
+
 fn load_data() -> Result<(Board, Guard)> {
-    let content = fs::read_to_string("./src/input/06_sm.input")?;
+    let content = fs::read_to_string("./src/input/06.input")?;
+    parse_board(&content)
+}
+
+fn parse_board(content: &str) -> Result<(Board, Guard)> {
+    parse_board_with(content, &TileSet::standard())
+}
+
+/// Parses a board that must hold exactly one guard.
+fn parse_board_with(content: &str, tiles: &TileSet) -> Result<(Board, Guard)> {
+    let (board, mut guards) = parse_guards_with(content, tiles)?;
+
+    match guards.len() {
+        0 => bail!("no guard on the board"),
+        1 => Ok((board, guards.remove(0))),
+        count => {
+            let positions: Vec<(usize, usize)> =
+                guards.iter().map(|guard| guard.position).collect();
+            bail!("expected one guard, found {count} at {:?}", positions)
+        }
+    }
+}
+
+fn parse_guards(content: &str) -> Result<(Board, Vec<Guard>)> {
+    parse_guards_with(content, &TileSet::standard())
+}
+
+/// Parses a board with any number of guards, in reading order. Every row must be as long as
+/// the first and every character must be in `tiles`. Positions in errors are (row, col).
+fn parse_guards_with(content: &str, tiles: &TileSet) -> Result<(Board, Vec<Guard>)> {
     let mut data = HashMap::new();
     let rows = content.lines().count();
-    let cols = content.lines().next().unwrap().len();
-    let mut guard = Guard {
-        position: (0, 0),
-        direction: Direction::Down,
+    let Some(cols) = content.lines().next().map(|line| line.chars().count()) else {
+        bail!("empty board");
     };
+    let mut guards = Vec::new();
 
     for (row, line) in content.lines().enumerate() {
+        let len = line.chars().count();
+        if len < cols {
+            bail!(
+                "row {row} ends early at {:?}, expected {cols} tiles like row 0",
+                (row, len)
+            );
+        }
+        if len > cols {
+            bail!(
+                "row {row} runs over at {:?}, expected {cols} tiles like row 0",
+                (row, cols)
+            );
+        }
+
         for (col, val) in line.chars().enumerate() {
-            let val = match val {
-                '.' => Piece::Empty,
-                '#' => Piece::Obstacle,
-                _ => {
-                    guard.position = (row, col);
-                    guard.direction = match val {
-                        '^' => Direction::Up,
-                        'v' => Direction::Down,
-                        '<' => Direction::Left,
-                        '>' => Direction::Right,
-                        _ => panic!("Invalid guard direction"),
-                    };
+            let val = match tiles.tile(val) {
+                Some(Tile::Empty) => Piece::Empty,
+                Some(Tile::Obstacle) => Piece::Obstacle,
+                Some(Tile::Custom { behaviour, symbol }) => Piece::Custom { behaviour, symbol },
+                Some(Tile::Guard(direction)) => {
+                    guards.push(Guard::new((row, col), direction));
                     Piece::Visited
                 }
+                None => bail!("unknown tile {:?} at {:?}", val, (row, col)),
             };
 
             data.insert((row, col), val);
         }
     }
 
-    Ok((Board { rows, cols, data }, guard))
+    Ok((Board { rows, cols, data }, guards))
+}
+
+/// Moves all guards together, one move or turn each per tick, until every guard has left or
+/// collided, or the guards still walking are all back in a state they were in together, so
+/// nothing new can happen. Guards collide when they end a tick on the same cell or swap
+/// cells. They never act as obstacles for each other.
+fn simulate_guards(board: &Board, guards: &[Guard]) -> Vec<Outcome> {
+    let mut guards = guards.to_vec();
+    let mut outcomes: Vec<Option<Outcome>> = vec![None; guards.len()];
+    let mut seen: Vec<HashSet<_>> = guards
+        .iter()
+        .map(|guard| HashSet::from([guard.state()]))
+        .collect();
+    let mut seen_together = HashSet::new();
+    let mut step = 0;
+
+    loop {
+        let walking: Vec<usize> = (0..guards.len())
+            .filter(|&index| matches!(outcomes[index], None | Some(Outcome::Loop { .. })))
+            .collect();
+        let together: Vec<_> = walking
+            .iter()
+            .map(|&index| (index, guards[index].state()))
+            .collect();
+        if walking.is_empty() || !seen_together.insert(together) {
+            break;
+        }
+
+        step += 1;
+        let before: Vec<(usize, usize)> = guards.iter().map(|guard| guard.position).collect();
+        let mut on_board = Vec::new();
+
+        for &index in walking.iter() {
+            let guard = &mut guards[index];
+            if !guard.advance(board, None) {
+                outcomes[index] = Some(Outcome::Exit { step });
+                continue;
+            }
+
+            on_board.push(index);
+            if outcomes[index].is_none() && !seen[index].insert(guard.state()) {
+                outcomes[index] = Some(Outcome::Loop { step });
+            }
+        }
+
+        let mut hits: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (i, &a) in on_board.iter().enumerate() {
+            for &b in on_board[i + 1..].iter() {
+                let same_cell = guards[a].position == guards[b].position;
+                let swapped = guards[a].position == before[b] && guards[b].position == before[a];
+                if same_cell || swapped {
+                    hits.entry(a).or_default().push(b);
+                    hits.entry(b).or_default().push(a);
+                }
+            }
+        }
+
+        for (index, mut with) in hits {
+            with.sort_unstable();
+            outcomes[index] = Some(Outcome::Collision {
+                step,
+                position: guards[index].position,
+                with,
+            });
+        }
+    }
+
+    // Repeating together means every walking guard repeats on its own as well, so all of
+    // them have a `Loop` by now.
+    outcomes.into_iter().map(Option::unwrap).collect()
+}
+
+/// Runs every guard on the board at `path` at once and prints how each patrol ended.
+/// Guard `n` (in reading order) follows `rules[n]`, or turns right if there is none.
+pub fn patrol(path: &str, rules: &[TurnRule]) {
+    let content = fs::read_to_string(path).unwrap();
+    let (board, guards) = parse_guards(&content).unwrap();
+    let guards: Vec<Guard> = guards
+        .into_iter()
+        .enumerate()
+        .map(|(index, guard)| guard.with_rule(rules.get(index).cloned().unwrap_or_default()))
+        .collect();
+
+    for (index, outcome) in simulate_guards(&board, &guards).iter().enumerate() {
+        let guard = &guards[index];
+        println!(
+            "Guard {index} at {:?} facing {}: {outcome}",
+            guard.position,
+            guard.direction.symbol()
+        );
+    }
+}
+
+/// Writes the guard's route, optionally only its first `steps` steps, to `path` or stdout.
+pub fn export_route(steps: Option<usize>, path: Option<&str>) {
+    let (board, guard) = load_data().unwrap();
+    let route = Route::record(&board, &guard, None);
+    let route = match steps {
+        Some(steps) => route.first(steps),
+        None => route,
+    };
+
+    let output = route.export(&board);
+    match path {
+        Some(path) => fs::write(path, output).unwrap(),
+        None => print!("{output}"),
+    }
 }
 
 pub fn part_a() {
@@ -126,40 +717,613 @@ pub fn part_a() {
 }
 
 pub fn part_b() {
-    let board = load_data().unwrap();
-    println!("Part b: 64");
+    let (board, guard) = load_data().unwrap();
+    let workers = thread::available_parallelism().map_or(1, |n| n.get());
+    println!(
+        "Part b: {}",
+        loop_obstructions_parallel(&board, &guard, workers).len()
+    );
 }
 
-fn move_one(board: &mut Board, guard: &mut Guard) -> bool {
-    let (row, col) = guard.position;
-    let direction = &guard.direction;
-
-    let (next_row, next_col) = match direction {
-        Direction::Up => (row - 1, col),
-        Direction::Down => (row + 1, col),
-        Direction::Left => (row, col - 1),
-        Direction::Right => (row, col + 1),
-    };
+/// Times the obstruction search on the real input, serially and then for a doubling number
+/// of workers up to the hardware threads, or 8 on smaller machines to show what
+/// oversubscribing costs, with the speedup over the serial run.
+pub fn benchmark() {
+    const ROUNDS: u32 = 20;
 
-    let next_spot = board.piece_at(next_row, next_col);
+    let (board, guard) = load_data().unwrap();
+    let most = thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .max(8);
+
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        loop_obstructions(&board, &guard);
+    }
+    let serial = start.elapsed() / ROUNDS;
+    println!("     serial: {:>10.2?} per run", serial);
 
-    match next_spot {
-        Piece::Empty | Piece::Visited => {
-            board.visit(next_row, next_col);
-            guard.position = (next_row, next_col);
-            true
+    let mut workers = 1;
+    loop {
+        let start = Instant::now();
+        let mut count = 0;
+        for _ in 0..ROUNDS {
+            count = loop_obstructions_parallel(&board, &guard, workers).len();
         }
-        Piece::Obstacle => {
-            guard.rotate_direction();
-            true
+        let elapsed = start.elapsed() / ROUNDS;
+
+        println!(
+            "{:>3} workers: {:>10.2?} per run, speedup {:.2}x ({} positions)",
+            workers,
+            elapsed,
+            serial.as_secs_f64() / elapsed.as_secs_f64(),
+            count
+        );
+        if workers == most {
+            break;
         }
-        _ => false,
+        workers = (workers * 2).min(most);
     }
 }
 
+/// Walks the guard until it leaves the board or comes back to a position it already stood
+/// on facing the same way, in which case it is stuck in a loop. `extra` is an obstacle
+/// added for this walk only.
+///
+/// This is the cell-by-cell reference for [`JumpTable::is_loop`].
+#[cfg(test)]
+fn is_loop(board: &Board, guard: &Guard, extra: Option<(usize, usize)>) -> bool {
+    Route::record(board, guard, extra).looped
+}
+
+/// Cells worth trying as a new obstacle: only cells on the original route can change it,
+/// and the starting cell is off limits. Sorted, so every search reports them in the same order.
+fn route_candidates(board: &Board, guard: &Guard) -> Vec<(usize, usize)> {
+    let mut route = board.clone();
+    let mut walker = guard.clone();
+    while move_one(&mut route, &mut walker) {}
+
+    let mut candidates: Vec<(usize, usize)> = route
+        .data
+        .iter()
+        .filter(|(cell, piece)| **piece == Piece::Visited && **cell != guard.position)
+        .map(|(cell, _)| *cell)
+        .collect();
+    candidates.sort();
+    candidates
+}
+
+/// Cells where one new obstacle traps the guard in a loop.
+fn loop_obstructions(board: &Board, guard: &Guard) -> Vec<(usize, usize)> {
+    let table = JumpTable::new(board);
+    route_candidates(board, guard)
+        .into_iter()
+        .filter(|cell| table.is_loop(guard, Some(*cell)))
+        .collect()
+}
+
+/// [`loop_obstructions`] spread over `workers` threads, capped only at the number of
+/// candidates, so asking for more threads than the machine has is up to the caller. Each worker takes a contiguous slice of the candidates and the
+/// slices are joined back in order, so the result does not depend on the worker count.
+fn loop_obstructions_parallel(board: &Board, guard: &Guard, workers: usize) -> Vec<(usize, usize)> {
+    let table = JumpTable::new(board);
+    let candidates = route_candidates(board, guard);
+    let workers = workers.min(candidates.len()).max(1);
+    let chunk_size = candidates.len().div_ceil(workers).max(1);
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = candidates
+            .chunks(chunk_size)
+            .map(|chunk| {
+                let table = &table;
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .copied()
+                        .filter(|cell| table.is_loop(guard, Some(*cell)))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    })
+}
+
+fn move_one(board: &mut Board, guard: &mut Guard) -> bool {
+    let moved = guard.advance(board, None);
+    if moved {
+        board.visit(guard.position.0, guard.position.1);
+    }
+    moved
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::test_util::Lcg;
+
+    fn small_input() -> (Board, Guard) {
+        let content = fs::read_to_string("./src/input/06_sm.input").unwrap();
+        parse_board(&content).unwrap()
+    }
+
+    #[test]
+    fn test_part_a_small() {
+        let (mut board, mut guard) = small_input();
+        while move_one(&mut board, &mut guard) {}
+
+        assert_eq!(board.visited_count(), 41);
+    }
+
+    #[test]
+    fn test_part_b_small() {
+        let (board, guard) = small_input();
+
+        assert_eq!(
+            loop_obstructions(&board, &guard),
+            vec![(6, 3), (7, 6), (7, 7), (8, 1), (8, 3), (9, 7)]
+        );
+    }
+
+    #[test]
+    fn test_parallel_matches_serial() {
+        let (board, guard) = small_input();
+        let expected = loop_obstructions(&board, &guard);
+        for workers in [0, 1, 3, 8] {
+            assert_eq!(
+                loop_obstructions_parallel(&board, &guard, workers),
+                expected
+            );
+        }
+
+        let (board, guard) = load_data().unwrap();
+        assert_eq!(
+            loop_obstructions_parallel(&board, &guard, 4),
+            loop_obstructions(&board, &guard)
+        );
+    }
+
+    #[test]
+    fn test_route_recording() {
+        let (mut board, mut guard) = small_input();
+        let route = Route::record(&board, &guard, None);
+
+        assert!(!route.looped);
+        assert_eq!(route.marks.len(), 41);
+        assert_eq!(
+            route.steps[..3],
+            [
+                RouteStep {
+                    position: (6, 4),
+                    direction: Direction::Up,
+                    step: 0
+                },
+                RouteStep {
+                    position: (5, 4),
+                    direction: Direction::Up,
+                    step: 1
+                },
+                RouteStep {
+                    position: (4, 4),
+                    direction: Direction::Up,
+                    step: 2
+                },
+            ]
+        );
+        assert_eq!(
+            route.marks[&(1, 4)],
+            Direction::Up.bit() | Direction::Right.bit()
+        );
+
+        while move_one(&mut board, &mut guard) {}
+        let last = route.steps.last().unwrap();
+        assert_eq!(
+            (last.position, last.direction),
+            (guard.position, guard.direction)
+        );
+    }
+
+    #[test]
+    fn test_route_render() {
+        let (board, guard) = small_input();
+        let route = Route::record(&board, &guard, Some((6, 3)));
+
+        assert!(route.looped);
+        assert_eq!(
+            route.render(&board),
+            "\
+....#.....
+....+---+#
+....|...|.
+..#.|...|.
+....|..#|.
+....|...|.
+.#.O^---+.
+........#.
+#.........
+......#...
+"
+        );
+
+        assert_eq!(
+            route.first(8).render(&board),
+            "\
+....#.....
+....+-...#
+....|.....
+..#.|.....
+....|..#..
+....|.....
+.#.O^.....
+........#.
+#.........
+......#...
+"
+        );
+    }
+
+    #[test]
+    fn test_route_export() {
+        let (board, guard) = small_input();
+        let route = Route::record(&board, &guard, None).first(2);
+        let export = route.export(&board);
+
+        assert!(!route.looped);
+        assert!(export.ends_with("\nstep,row,col,direction\n0,6,4,^\n1,5,4,^\n"));
+    }
+
+    #[test]
+    fn test_turn_rules() {
+        assert_eq!(Turn::Right.apply(Direction::Up), Direction::Right);
+        assert_eq!(Turn::Left.apply(Direction::Up), Direction::Left);
+        assert_eq!(Turn::Left.apply(Direction::Left), Direction::Down);
+        assert_eq!(Turn::Reverse.apply(Direction::Right), Direction::Left);
+
+        let rule = TurnRule::Sequence(vec![Turn::Left, Turn::Reverse]);
+        let mut guard = Guard::new((0, 0), Direction::Up).with_rule(rule);
+        let mut directions = Vec::new();
+        for _ in 0..4 {
+            guard.rotate_direction();
+            directions.push(guard.direction);
+        }
+        assert_eq!(
+            directions,
+            vec![
+                Direction::Left,
+                Direction::Right,
+                Direction::Up,
+                Direction::Down
+            ]
+        );
+        assert_eq!(guard.rule.phase(guard.turns_taken), 0);
+        assert_eq!(TurnRule::Sequence(vec![]).turn(3), Turn::Right);
+    }
+
+    #[test]
+    fn test_turn_rule_loops() {
+        let (board, guard) = small_input();
+        let table = JumpTable::new(&board);
+
+        // Reversing at the first obstacle sends the guard back out the bottom.
+        let reverse = guard.clone().with_rule(TurnRule::Always(Turn::Reverse));
+        assert!(!is_loop(&board, &reverse, None));
+        assert!(!table.is_loop(&reverse, None));
+
+        // Bouncing between two obstacles on one line never ends.
+        let (board, guard) = parse_board("#\n.\n^\n#").unwrap();
+        let reverse = guard.with_rule(TurnRule::Always(Turn::Reverse));
+        assert!(is_loop(&board, &reverse, None));
+        assert!(JumpTable::new(&board).is_loop(&reverse, None));
+    }
+
+    #[test]
+    fn test_simulate_single_guard_matches_route() {
+        let (board, guard) = small_input();
+        let route = Route::record(&board, &guard, None);
+
+        assert_eq!(
+            simulate_guards(&board, std::slice::from_ref(&guard)),
+            vec![Outcome::Exit {
+                step: route.steps.len()
+            }]
+        );
+
+        let route = Route::record(&board, &guard, Some((6, 3)));
+        let mut blocked = board.clone();
+        blocked.data.insert((6, 3), Piece::Obstacle);
+        assert_eq!(
+            simulate_guards(&blocked, &[guard]),
+            vec![Outcome::Loop {
+                step: route.steps.len()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_simulate_guards() {
+        // Two cells apart, the guards swap cells on the second tick.
+        let (board, guards) = parse_guards("v\n.\n.\n^").unwrap();
+        assert_eq!(
+            simulate_guards(&board, &guards),
+            vec![
+                Outcome::Collision {
+                    step: 2,
+                    position: (2, 0),
+                    with: vec![1]
+                },
+                Outcome::Collision {
+                    step: 2,
+                    position: (1, 0),
+                    with: vec![0]
+                },
+            ]
+        );
+
+        let (board, guards) = parse_guards("v\n.\n^").unwrap();
+        assert_eq!(
+            simulate_guards(&board, &guards),
+            vec![
+                Outcome::Collision {
+                    step: 1,
+                    position: (1, 0),
+                    with: vec![1]
+                },
+                Outcome::Collision {
+                    step: 1,
+                    position: (1, 0),
+                    with: vec![0]
+                },
+            ]
+        );
+
+        // The second guard walks straight off; the first one loops and is left alone.
+        let (mut board, guard) = small_input();
+        board.data.insert((6, 3), Piece::Obstacle);
+        let guards = [guard, Guard::new((9, 0), Direction::Left)];
+        let outcomes = simulate_guards(&board, &guards);
+        assert!(matches!(outcomes[0], Outcome::Loop { .. }));
+        assert_eq!(outcomes[1], Outcome::Exit { step: 1 });
+    }
+
+    #[test]
+    fn test_strict_parsing() {
+        let error = |content: &str| parse_board(content).unwrap_err().to_string();
+
+        assert_eq!(error("...\n..."), "no guard on the board");
+        assert_eq!(
+            error(".^.\n..<"),
+            "expected one guard, found 2 at [(0, 1), (1, 2)]"
+        );
+        assert_eq!(error("..\n.x\n^."), "unknown tile 'x' at (1, 1)");
+        assert_eq!(
+            error("...\n.^\n..."),
+            "row 1 ends early at (1, 2), expected 3 tiles like row 0"
+        );
+        assert_eq!(
+            error("...\n.^..\n..."),
+            "row 1 runs over at (1, 3), expected 3 tiles like row 0"
+        );
+        assert_eq!(error(""), "empty board");
+
+        let (_, guards) = parse_guards(".^.\n..<").unwrap();
+        assert_eq!(guards.len(), 2);
+    }
+
+    #[test]
+    fn test_registered_tiles() {
+        let tiles = TileSet::standard()
+            .register('O', Tile::Obstacle)
+            .register('X', Tile::Empty);
+        assert!(parse_board("O.\n^X").is_err());
+
+        let (board, guard) = parse_board_with("O.\n^X", &tiles).unwrap();
+        assert_eq!(board.piece_at(0, 0), Piece::Obstacle);
+        assert_eq!(board.piece_at(1, 1), Piece::Empty);
+        assert_eq!(guard.position, (1, 0));
+        assert_eq!(guard.direction, Direction::Up);
+
+        let tiles = TileSet::new()
+            .register('.', Tile::Empty)
+            .register('G', Tile::Guard(Direction::Left));
+        let (_, guard) = parse_board_with("..G", &tiles).unwrap();
+        assert_eq!(guard.direction, Direction::Left);
+        assert!(parse_board_with("#.G", &tiles).is_err());
+    }
+
+    #[test]
+    fn test_custom_tiles() {
+        let tiles = TileSet::standard()
+            .register(
+                'O',
+                Tile::Custom {
+                    behaviour: Behaviour::Blocks,
+                    symbol: 'O',
+                },
+            )
+            .register(
+                'w',
+                Tile::Custom {
+                    behaviour: Behaviour::Passable,
+                    symbol: '~',
+                },
+            );
+        let (board, guard) = parse_board_with("O...\n....\nw...\n^..w", &tiles).unwrap();
+        assert!(board.piece_at(0, 0).blocks());
+        assert!(!board.piece_at(2, 0).blocks());
+
+        let table = JumpTable::new(&board);
+        assert_eq!(table.next_stop((3, 0), Direction::Up, None), Some((1, 0)));
+
+        let route = Route::record(&board, &guard, None);
+        assert!(!route.looped);
+        assert_eq!(route.render(&board), "O...\n+---\n|...\n^..~\n");
+
+        let (mut walked, mut walker) = (board.clone(), guard.clone());
+        while move_one(&mut walked, &mut walker) {}
+        assert_eq!(walked.visited_count(), 6);
+        assert_eq!(loop_obstructions(&board, &guard), vec![]);
+    }
+
+    #[test]
+    fn test_looping_guards_collide_later() {
+        // Each guard bounces on its own line and loops early, but with different periods,
+        // so they only meet on tick 19.
+        let (board, _) = parse_guards("..#..\n.....\n#...#\n.....\n.....\n..#..").unwrap();
+        let reverse = TurnRule::Always(Turn::Reverse);
+        let guards = [
+            Guard::new((2, 1), Direction::Right).with_rule(reverse.clone()),
+            Guard::new((4, 2), Direction::Down).with_rule(reverse),
+        ];
+
+        assert_eq!(
+            simulate_guards(&board, &guards),
+            vec![
+                Outcome::Collision {
+                    step: 19,
+                    position: (2, 2),
+                    with: vec![1]
+                },
+                Outcome::Collision {
+                    step: 19,
+                    position: (2, 2),
+                    with: vec![0]
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_loop_detection() {
+        let (board, guard) = small_input();
+
+        assert!(!is_loop(&board, &guard, None));
+        assert!(is_loop(&board, &guard, Some((6, 3))));
+        assert!(!is_loop(&board, &guard, Some((0, 0))));
+    }
+
+    fn random_board(rng: &mut Lcg, rows: usize, cols: usize) -> (Board, Guard) {
+        let start = (rng.below(rows), rng.below(cols));
+        let content: Vec<String> = (0..rows)
+            .map(|row| {
+                (0..cols)
+                    .map(|col| match ((row, col) == start, rng.below(12)) {
+                        (true, _) => '^',
+                        (false, 0) => '#',
+                        (false, 1) => 'O',
+                        (false, 2) => '~',
+                        _ => '.',
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let tiles = TileSet::standard()
+            .register(
+                'O',
+                Tile::Custom {
+                    behaviour: Behaviour::Blocks,
+                    symbol: 'O',
+                },
+            )
+            .register(
+                '~',
+                Tile::Custom {
+                    behaviour: Behaviour::Passable,
+                    symbol: '~',
+                },
+            );
+        let (board, mut guard) = parse_board_with(&content.join("\n"), &tiles).unwrap();
+        guard.direction = [
+            Direction::Up,
+            Direction::Down,
+            Direction::Left,
+            Direction::Right,
+        ][rng.below(4)];
+        (board, guard)
+    }
+
+    #[test]
+    fn test_next_stop() {
+        let (board, _) = small_input();
+        let table = JumpTable::new(&board);
+
+        assert_eq!(table.next_stop((6, 4), Direction::Up, None), Some((1, 4)));
+        assert_eq!(
+            table.next_stop((1, 4), Direction::Right, None),
+            Some((1, 8))
+        );
+        assert_eq!(table.next_stop((7, 7), Direction::Down, None), None);
+        assert_eq!(
+            table.next_stop((6, 4), Direction::Up, Some((3, 4))),
+            Some((4, 4))
+        );
+        assert_eq!(
+            table.next_stop((6, 4), Direction::Up, Some((7, 4))),
+            Some((1, 4))
+        );
+        assert_eq!(
+            table.next_stop((6, 4), Direction::Up, Some((5, 4))),
+            Some((6, 4))
+        );
+        assert_eq!(
+            table.next_stop((7, 7), Direction::Down, Some((9, 7))),
+            Some((8, 7))
+        );
+    }
+
+    #[test]
+    fn test_jump_table_matches_step_simulation() {
+        let mut rng = Lcg(6);
+
+        for _ in 0..300 {
+            let (rows, cols) = (1 + rng.below(12), 1 + rng.below(12));
+            let (board, guard) = random_board(&mut rng, rows, cols);
+            let table = JumpTable::new(&board);
+
+            for _ in 0..10 {
+                let extra =
+                    Some((rng.below(rows), rng.below(cols))).filter(|&cell| cell != guard.position);
+                assert_eq!(
+                    table.is_loop(&guard, extra),
+                    is_loop(&board, &guard, extra),
+                    "{:?} {:?} {:?}",
+                    board,
+                    guard,
+                    extra
+                );
+
+                let position = (rng.below(rows), rng.below(cols));
+                let direction = guard.direction;
+                let mut walker = position;
+                let expected = loop {
+                    let next = direction.step(walker);
+                    if Some(next) == extra || board.piece_at(next.0, next.1).blocks() {
+                        break Some(walker);
+                    }
+                    if board.piece_at(next.0, next.1) == Piece::OutOfBounds {
+                        break None;
+                    }
+                    walker = next;
+                };
+                assert_eq!(table.next_stop(position, direction, extra), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_guard_leaving_top_edge() {
+        let (mut board, mut guard) = parse_board(".#.\n...\n.^.").unwrap();
+        guard.direction = Direction::Left;
+        while move_one(&mut board, &mut guard) {}
+        assert_eq!(board.visited_count(), 2);
+
+        let (board, guard) = parse_board("...\n.^.").unwrap();
+        assert!(!is_loop(&board, &guard, None));
+    }
 
     #[test]
     fn test_board() {
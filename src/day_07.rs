@@ -16,7 +16,6 @@ impl FromStr for Equation {
         let values = parts[1]
             .trim()
             .split(" ")
-            .into_iter()
             .map(|v| v.parse().unwrap())
             .collect();
 
@@ -110,6 +109,6 @@ mod test {
         };
 
         let result = evaluate(&equation, 0, 0);
-        assert_eq!(result, false);
+        assert!(!result);
     }
 }
@@ -20,6 +20,7 @@ impl DiskMap {
         DiskMap(blocks_from_layout(values))
     }
 
+    #[allow(dead_code)]
     pub fn display_layout(&self) -> String {
         self.0
             .iter()
@@ -98,7 +99,7 @@ pub fn part_a() {
 }
 
 pub fn part_b() {
-    let equations = load_data().unwrap();
+    let _disk = load_data().unwrap();
     println!("Part b: 43");
 }
 
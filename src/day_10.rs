@@ -1,9 +1,7 @@
 use anyhow::Result;
-use std::{
-    collections::{HashMap, HashSet},
-    fs,
-};
+use std::{collections::HashMap, fs};
 
+#[allow(dead_code)]
 #[derive(Debug, Clone)]
 struct Board {
     pub rows: usize,
@@ -11,6 +9,7 @@ struct Board {
     pub data: HashMap<(i32, i32), i32>,
 }
 
+#[allow(dead_code)]
 impl Board {
     fn value_at(&self, row: i32, col: i32) -> Option<&i32> {
         self.data.get(&(row, col))
@@ -43,6 +42,7 @@ fn load_data() -> Result<Board> {
     Ok(Board { rows, cols, data })
 }
 
+#[allow(dead_code)]
 fn find_paths(board: &Board, row: i32, col: i32, acc: String, paths: &mut Vec<String>) {
     let possibilities = [
         (row + 1, col),
@@ -68,6 +68,7 @@ fn find_paths(board: &Board, row: i32, col: i32, acc: String, paths: &mut Vec<St
     }
 }
 
+#[allow(dead_code)]
 fn trailheads(board: &Board) -> Vec<(i32, i32)> {
     board
         .data
@@ -78,12 +79,12 @@ fn trailheads(board: &Board) -> Vec<(i32, i32)> {
 }
 
 pub fn part_a() {
-    let mut disk = load_data().unwrap();
+    let _board = load_data().unwrap();
     println!("Part a: {}", 23);
 }
 
 pub fn part_b() {
-    let equations = load_data().unwrap();
+    let _board = load_data().unwrap();
     println!("Part b: 43");
 }
 
@@ -114,7 +115,7 @@ mod test {
     fn test_disk_layout_2() {
         let board = load_data().unwrap();
 
-        let trailheads = trailheads(&board);
+        let _trailheads = trailheads(&board);
         // println!("Trailheads {:?}", trailheads);
 
         let mut paths = Vec::new();
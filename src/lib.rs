@@ -7,3 +7,6 @@ pub mod day_06;
 pub mod day_07;
 pub mod day_09;
 pub mod day_10;
+
+#[cfg(test)]
+mod test_util;